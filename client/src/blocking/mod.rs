@@ -0,0 +1,129 @@
+//! Blocking client of the CAPTCHA service. It must not be used within an async runtime.
+
+use std::thread;
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+
+use crate::{CaptchaSolutionResponse, CaptchaStatus, ClientError, Config, NewCaptchaRequest, NewCaptchaResponse, RedeemResponse, Retry};
+use crate::{parse, redeem_path, solution_path, status_path};
+use crate::mock::MockServer;
+
+/// Blocking client of the CAPTCHA service. It has the same methods as the async `Client`.
+#[derive(Clone)]
+pub struct Client {
+    config: Config,
+    http: reqwest::blocking::Client,
+}
+
+impl Client {
+    /// Creates a client for the service at `base_url`, e.g. `http://localhost:8080`.
+    pub fn new(base_url: &str) -> Client {
+        Client {
+            config: Config::new(base_url),
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    /// Creates a client which sends its requests to `server` instead of a running service.
+    pub fn mock(server: MockServer) -> Client {
+        let mut c = Client::new("http://mock");
+        c.config.mock = Some(server);
+        c
+    }
+
+    /// Sends the client id in the header `X-Client-ID` with each request.
+    pub fn client_id(mut self, id: &str) -> Client {
+        self.config.client_id = Some(id.to_string());
+        self
+    }
+
+    /// Sends the secret of the site in the header `X-Site-Secret`. It is needed to query the
+    /// status of the CAPTCHAs of a registered site.
+    pub fn secret(mut self, secret: &str) -> Client {
+        self.config.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Retries a failed request up to `retries` times (default: 2). The time between two
+    /// attempts starts at `backoff` (default: 100ms) and doubles with each attempt.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Client {
+        self.config.retries = retries;
+        self.config.backoff = backoff;
+        self
+    }
+
+    /// Creates a new CAPTCHA.
+    pub fn new_captcha(&self, r: &NewCaptchaRequest) -> Result<NewCaptchaResponse, ClientError> {
+        self.call(Method::POST, &r.path(), Retry::Unprocessed)
+    }
+
+    /// Checks the solution of a CAPTCHA. Each check consumes a try.
+    pub fn check_solution(&self, id: &str, solution: &str) -> Result<CaptchaSolutionResponse, ClientError> {
+        self.call(Method::POST, &solution_path(id, solution), Retry::Unprocessed)
+    }
+
+    /// Returns the state of a CAPTCHA without consuming a try.
+    pub fn status(&self, id: &str) -> Result<CaptchaStatus, ClientError> {
+        self.call(Method::GET, &status_path(id), Retry::Idempotent)
+    }
+
+    /// Redeems the verification token of a solved CAPTCHA, e.g. from the widget. Each token can
+    /// be redeemed once.
+    pub fn redeem(&self, token: &str) -> Result<RedeemResponse, ClientError> {
+        self.call(Method::POST, &redeem_path(token), Retry::Unprocessed)
+    }
+
+    fn call<T: DeserializeOwned>(&self, method: Method, path: &str, retry: Retry) -> Result<T, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let r = self.send(method.clone(), path).and_then(|(status, body)| parse(status, &body));
+            match r {
+                Err(ref e) if attempt < self.config.retries && retry.retryable(e) => {
+                    attempt += 1;
+                    thread::sleep(self.config.delay(attempt));
+                },
+                _ => return r
+            }
+        }
+    }
+
+    fn send(&self, method: Method, path: &str) -> Result<(u16, String), ClientError> {
+        if let Some(ref m) = self.config.mock {
+            return Ok(m.handle(method.as_str(), path));
+        }
+        let mut req = self.http.request(method, self.config.url(path));
+        if let Some(ref id) = self.config.client_id {
+            req = req.header("X-Client-ID", id.as_str());
+        }
+        if let Some(ref secret) = self.config.secret {
+            req = req.header("X-Site-Secret", secret.as_str());
+        }
+        let res = req.send()?;
+        let status = res.status().as_u16();
+        Ok((status, res.text()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::blocking::Client;
+    use crate::{ClientError, Difficulty, NewCaptchaRequest};
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_blocking_mock() {
+        let server = MockServer::new();
+        let client = Client::mock(server.clone()).retries(1, Duration::from_millis(1));
+
+        let c = client.new_captcha(&NewCaptchaRequest::new(Difficulty::Medium, 1, 60)).unwrap();
+        assert_eq!(client.status(&c.id).unwrap().tries_left(), 1);
+
+        server.fail_next(2);
+        assert!(matches!(client.status(&c.id), Err(ClientError::Service { code: 1, .. })));
+        assert!(client.check_solution(&c.id, &c.solution).unwrap().accepted());
+    }
+}
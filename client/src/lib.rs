@@ -0,0 +1,349 @@
+//! Client library for the CAPTCHA service.
+//!
+//! `Client` is the async client, `blocking::Client` the blocking one. Both wrap the responses of
+//! the service in the types of `rust_captcha_types` and retry failed requests. Tests can use
+//! `mock::MockServer` instead of a running service. The mock runs in the process of the test and
+//! is only reachable through `Client::mock` and `blocking::Client::mock`, not over HTTP.
+//!
+//! ```no_run
+//! use rust_captcha_client::{Client, Difficulty, NewCaptchaRequest};
+//!
+//! # async fn example() -> Result<(), rust_captcha_client::ClientError> {
+//! let client = Client::new("http://localhost:8000").client_id("shop");
+//! let captcha = client.new_captcha(&NewCaptchaRequest::new(Difficulty::Easy, 3, 60)).await?;
+//! let check = client.check_solution(&captcha.id, "abcd").await?;
+//! println!("{}", check.accepted());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+
+pub use rust_captcha_types::{CaptchaSolutionResponse, CaptchaState, CaptchaStatus, Difficulty, RedeemResponse, Verdict};
+pub use rust_captcha_types::{Envelope, NewCaptchaRequest, NewCaptchaResponse, error_code};
+
+use crate::mock::MockServer;
+
+pub mod blocking;
+pub mod mock;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientError {
+    /// No connection to the service could be established.
+    Connect(String),
+    /// The request failed after the connection was established.
+    Http(String),
+    /// The service answered with an unexpected HTTP status.
+    Status(u16),
+    /// The response is not a valid envelope.
+    InvalidResponse(String),
+    /// The CAPTCHA does not exist (`error_code::NOT_FOUND`), e.g. because it has expired a while
+    /// ago.
+    NotFound,
+    /// The service answered with another error code than `error_code::PROCESSED`.
+    Service { code: u32, message: String },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Connect(e)         => write!(f, "cannot connect to service: {}", e),
+            ClientError::Http(e)            => write!(f, "request failed: {}", e),
+            ClientError::Status(s)          => write!(f, "unexpected HTTP status {}", s),
+            ClientError::InvalidResponse(e) => write!(f, "invalid response: {}", e),
+            ClientError::NotFound           => write!(f, "CAPTCHA not found"),
+            ClientError::Service { code, message } => write!(f, "service error {}: {}", code, message),
+        }
+    }
+}
+
+impl error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> ClientError {
+        if e.is_connect() {
+            ClientError::Connect(e.to_string())
+        } else {
+            ClientError::Http(e.to_string())
+        }
+    }
+}
+
+/// Which failed requests are retried.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Retry {
+    /// Requests which can be repeated without side effects, e.g. status requests.
+    Idempotent,
+    /// Requests with side effects, e.g. new CAPTCHAs, which are stored and take tokens of the rate
+    /// limit, and solution checks, which consume a try. They are only retried if the service has
+    /// not processed them.
+    Unprocessed,
+}
+
+impl Retry {
+    pub(crate) fn retryable(self, e: &ClientError) -> bool {
+        let rejected = match e {
+            ClientError::Connect(_) => true,
+            ClientError::Status(429) => true,
+            ClientError::Service { code, .. } => *code == error_code::TOO_MANY_REQUESTS,
+            _ => false
+        };
+        rejected || (self == Retry::Idempotent && match e {
+            ClientError::Http(_) => true,
+            ClientError::Status(s) => *s >= 500,
+            ClientError::Service { code, .. } => *code == error_code::INTERNAL_ERROR,
+            _ => false
+        })
+    }
+}
+
+/// Settings which are shared by the async and the blocking client.
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) base_url: String,
+    pub(crate) client_id: Option<String>,
+    pub(crate) secret: Option<String>,
+    pub(crate) retries: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) mock: Option<MockServer>,
+}
+
+impl Config {
+    pub(crate) fn new(base_url: &str) -> Config {
+        Config {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client_id: None,
+            secret: None,
+            retries: 2,
+            backoff: Duration::from_millis(100),
+            mock: None,
+        }
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Returns the time to wait before the retry `attempt` (starting at 1). It doubles with each
+    /// attempt.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt.saturating_sub(1))
+    }
+}
+
+/// Unwraps the envelope of a response.
+pub(crate) fn parse<T: DeserializeOwned>(status: u16, body: &str) -> Result<T, ClientError> {
+    let envelope: Envelope<serde_json::Value> = match serde_json::from_str(body) {
+        Ok(e) => e,
+        Err(_) if status != 200 => return Err(ClientError::Status(status)),
+        Err(e) => return Err(ClientError::InvalidResponse(e.to_string()))
+    };
+    match envelope.error_code {
+        error_code::PROCESSED => {},
+        error_code::NOT_FOUND => return Err(ClientError::NotFound),
+        code => return Err(ClientError::Service { code, message: envelope.error_msg })
+    }
+    serde_json::from_value(envelope.result).map_err(|e| ClientError::InvalidResponse(e.to_string()))
+}
+
+/// Percent-encodes a path segment.
+pub(crate) fn segment(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b)
+        })
+        .collect()
+}
+
+pub(crate) fn solution_path(id: &str, solution: &str) -> String {
+    format!("/solution/{}/{}", segment(id), segment(solution))
+}
+
+pub(crate) fn status_path(id: &str) -> String {
+    format!("/captcha/{}/status", segment(id))
+}
+
+pub(crate) fn redeem_path(id: &str) -> String {
+    format!("/captcha/{}/redeem", segment(id))
+}
+
+/// Async client of the CAPTCHA service.
+#[derive(Clone)]
+pub struct Client {
+    config: Config,
+    http: reqwest::Client,
+}
+
+impl Client {
+    /// Creates a client for the service at `base_url`, e.g. `http://localhost:8080`.
+    pub fn new(base_url: &str) -> Client {
+        Client {
+            config: Config::new(base_url),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Creates a client which sends its requests to `server` instead of a running service.
+    pub fn mock(server: MockServer) -> Client {
+        let mut c = Client::new("http://mock");
+        c.config.mock = Some(server);
+        c
+    }
+
+    /// Sends the client id in the header `X-Client-ID` with each request.
+    pub fn client_id(mut self, id: &str) -> Client {
+        self.config.client_id = Some(id.to_string());
+        self
+    }
+
+    /// Sends the secret of the site in the header `X-Site-Secret`. It is needed to query the
+    /// status of the CAPTCHAs of a registered site and to redeem their tokens.
+    pub fn secret(mut self, secret: &str) -> Client {
+        self.config.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Retries a failed request up to `retries` times (default: 2). The time between two
+    /// attempts starts at `backoff` (default: 100ms) and doubles with each attempt.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Client {
+        self.config.retries = retries;
+        self.config.backoff = backoff;
+        self
+    }
+
+    /// Creates a new CAPTCHA.
+    pub async fn new_captcha(&self, r: &NewCaptchaRequest) -> Result<NewCaptchaResponse, ClientError> {
+        self.call(Method::POST, &r.path(), Retry::Unprocessed).await
+    }
+
+    /// Checks the solution of a CAPTCHA. Each check consumes a try.
+    pub async fn check_solution(&self, id: &str, solution: &str) -> Result<CaptchaSolutionResponse, ClientError> {
+        self.call(Method::POST, &solution_path(id, solution), Retry::Unprocessed).await
+    }
+
+    /// Returns the state of a CAPTCHA without consuming a try. Unknown CAPTCHAs and CAPTCHAs
+    /// whose tombstone has expired return `ClientError::NotFound`.
+    pub async fn status(&self, id: &str) -> Result<CaptchaStatus, ClientError> {
+        self.call(Method::GET, &status_path(id), Retry::Idempotent).await
+    }
+
+    /// Redeems the verification token of a solved CAPTCHA, e.g. from the widget. Each token can
+    /// be redeemed once.
+    pub async fn redeem(&self, token: &str) -> Result<RedeemResponse, ClientError> {
+        self.call(Method::POST, &redeem_path(token), Retry::Unprocessed).await
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: Method, path: &str, retry: Retry) -> Result<T, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let r = match self.send(method.clone(), path).await {
+                Ok((status, body)) => parse(status, &body),
+                Err(e) => Err(e)
+            };
+            match r {
+                Err(ref e) if attempt < self.config.retries && retry.retryable(e) => {
+                    attempt += 1;
+                    tokio::time::sleep(self.config.delay(attempt)).await;
+                },
+                _ => return r
+            }
+        }
+    }
+
+    async fn send(&self, method: Method, path: &str) -> Result<(u16, String), ClientError> {
+        if let Some(ref m) = self.config.mock {
+            return Ok(m.handle(method.as_str(), path));
+        }
+        let mut req = self.http.request(method, self.config.url(path));
+        if let Some(ref id) = self.config.client_id {
+            req = req.header("X-Client-ID", id.as_str());
+        }
+        if let Some(ref secret) = self.config.secret {
+            req = req.header("X-Site-Secret", secret.as_str());
+        }
+        let res = req.send().await?;
+        let status = res.status().as_u16();
+        Ok((status, res.text().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{Client, ClientError, Config, Difficulty, NewCaptchaRequest, Retry, parse, segment};
+    use crate::error_code;
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_parse() {
+        let ok = r#"{"error_code":0,"error_msg":"processed","result":{"id":"a","png":"b","solution":"c"}}"#;
+        let r: crate::NewCaptchaResponse = parse(200, ok).unwrap();
+        assert_eq!(r.solution, "c");
+
+        let err = r#"{"error_code":2,"error_msg":"invalid parameters","result":""}"#;
+        let r: Result<crate::NewCaptchaResponse, _> = parse(200, err);
+        assert_eq!(r.unwrap_err(), ClientError::Service { code: 2, message: String::from("invalid parameters") });
+
+        let err = r#"{"error_code":5,"error_msg":"not found","result":""}"#;
+        let r: Result<crate::CaptchaStatus, _> = parse(200, err);
+        assert_eq!(r.unwrap_err(), ClientError::NotFound);
+
+        let r: Result<crate::NewCaptchaResponse, _> = parse(502, "Bad Gateway");
+        assert_eq!(r.unwrap_err(), ClientError::Status(502));
+    }
+
+    #[test]
+    fn test_retryable() {
+        let internal = ClientError::Service { code: error_code::INTERNAL_ERROR, message: String::new() };
+        let limited = ClientError::Service { code: error_code::TOO_MANY_REQUESTS, message: String::new() };
+        assert!(Retry::Idempotent.retryable(&internal));
+        assert!(!Retry::Unprocessed.retryable(&internal));
+        assert!(Retry::Unprocessed.retryable(&limited));
+        assert!(Retry::Unprocessed.retryable(&ClientError::Connect(String::new())));
+        assert!(!Retry::Idempotent.retryable(&ClientError::Status(404)));
+    }
+
+    #[test]
+    fn test_config() {
+        let c = Config::new("http://localhost:8080/");
+        assert_eq!(c.url("/new/easy"), "http://localhost:8080/new/easy");
+        assert_eq!(c.delay(1), Duration::from_millis(100));
+        assert_eq!(c.delay(3), Duration::from_millis(400));
+        assert_eq!(segment("a b/ä"), "a%20b%2F%C3%A4");
+    }
+
+    #[tokio::test]
+    async fn test_mock() {
+        let server = MockServer::new();
+        let client = Client::mock(server.clone()).retries(2, Duration::from_millis(1));
+
+        let c = client.new_captcha(&NewCaptchaRequest::new(Difficulty::Easy, 2, 60)).await.unwrap();
+        let r = client.check_solution(&c.id, "wrong").await.unwrap();
+        assert!(!r.accepted());
+        assert_eq!(r.trials_left(), 1);
+        assert!(client.check_solution(&c.id, &c.solution).await.unwrap().accepted());
+        assert_eq!(client.status(&c.id).await.unwrap().state(), crate::CaptchaState::Solved);
+        assert!(client.redeem(&c.id).await.unwrap().redeemed());
+        assert!(!client.redeem(&c.id).await.unwrap().redeemed());
+        assert_eq!(client.check_solution(&c.id, &c.solution).await.unwrap().verdict(), crate::Verdict::AlreadyUsed);
+        assert_eq!(client.check_solution("unknown", "x").await.unwrap().verdict(), crate::Verdict::NotFound);
+        assert_eq!(client.status("unknown").await.unwrap_err(), ClientError::NotFound);
+
+        // Internal errors are retried for status requests but not for new CAPTCHAs and solution
+        // checks, which may have been processed.
+        server.fail_next(2);
+        assert!(client.status(&c.id).await.is_ok());
+        server.fail_next(1);
+        assert!(client.new_captcha(&NewCaptchaRequest::new(Difficulty::Hard, 1, 60)).await.is_err());
+        server.fail_next(1);
+        assert!(client.check_solution(&c.id, "x").await.is_err());
+        assert_eq!(server.requests(), 14);
+    }
+}
@@ -0,0 +1,191 @@
+//! In-memory replacement of the CAPTCHA service for tests.
+//!
+//! The mock answers the same routes as the service with the same envelopes, so a client which is
+//! created with `Client::mock` behaves like a client of a running service. The mock does not
+//! listen on a port: the requests of the client are passed to `MockServer::handle` within the
+//! process, so it cannot be used by other HTTP clients or processes.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_captcha_types::{CaptchaSolutionResponse, CaptchaState, CaptchaStatus, Envelope, NewCaptchaResponse, RedeemResponse, Verdict, error_code};
+use serde::Serialize;
+
+/// A 1x1 PNG which is returned as image of each CAPTCHA.
+const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+struct MockCaptcha {
+    solution: String,
+    tries_left: usize,
+    expires_at: i64,
+    solved: bool,
+    redeemed: bool,
+}
+
+#[derive(Default)]
+struct State {
+    captchas: HashMap<String, MockCaptcha>,
+    created: u64,
+    failures: u32,
+    requests: usize,
+}
+
+/// An in-memory CAPTCHA service. Clones share their state.
+#[derive(Clone)]
+pub struct MockServer {
+    solution: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MockServer {
+    fn default() -> MockServer {
+        MockServer::new()
+    }
+}
+
+impl MockServer {
+    /// Creates a mock whose CAPTCHAs have the solution `abcd`.
+    pub fn new() -> MockServer {
+        MockServer::with_solution("abcd")
+    }
+
+    /// Creates a mock whose CAPTCHAs have the given solution.
+    pub fn with_solution(solution: &str) -> MockServer {
+        MockServer {
+            solution: solution.to_string(),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Answers the next `n` requests with an internal error.
+    pub fn fail_next(&self, n: u32) {
+        self.state().failures = n;
+    }
+
+    /// Returns the number of requests the mock has received.
+    pub fn requests(&self) -> usize {
+        self.state().requests
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Handles a request and returns the HTTP status and the body of the response.
+    pub fn handle(&self, method: &str, path: &str) -> (u16, String) {
+        let mut state = self.state();
+        state.requests += 1;
+        if state.failures > 0 {
+            state.failures -= 1;
+            return error(error_code::INTERNAL_ERROR, "internal error");
+        }
+
+        let path = path.split('?').next().unwrap_or("");
+        let parts: Vec<&str> = path.split('/').skip(1).collect();
+        match (method, parts.as_slice()) {
+            ("POST", ["new", difficulty, max_tries, ttl]) => {
+                match (valid_difficulty(difficulty), max_tries.parse::<usize>(), ttl.parse::<i64>()) {
+                    (true, Ok(tries), Ok(ttl)) if tries > 0 && ttl > 0 => self.create(&mut state, tries, ttl),
+                    _ => error(error_code::INVALID_PARAMETERS, "invalid parameters")
+                }
+            },
+            ("GET", ["new", difficulty]) if valid_difficulty(difficulty) => self.create(&mut state, 3, 120),
+            ("POST", ["solution", id, solution]) => {
+                let now = now();
+                let v = match state.captchas.get_mut(*id) {
+                    Some(ref c) if c.solved => Verdict::AlreadyUsed,
+                    Some(ref c) if c.expires_at <= now => Verdict::Expired,
+                    Some(c) if c.tries_left == 0 => Verdict::Exhausted,
+                    Some(c) => {
+                        c.tries_left -= 1;
+                        if c.solution == *solution {
+                            c.solved = true;
+                            Verdict::Accepted
+                        } else {
+                            Verdict::Incorrect { tries_left: c.tries_left }
+                        }
+                    },
+                    None => Verdict::NotFound
+                };
+                let r = CaptchaSolutionResponse::new(v);
+                ok(&r)
+            },
+            ("GET", ["captcha", id, "status"]) => {
+                let now = now();
+                match state.captchas.get(*id) {
+                    Some(c) => {
+                        let s = match c.tries_left {
+                            _ if c.solved => CaptchaState::Solved,
+                            _ if c.expires_at <= now => CaptchaState::Expired,
+                            0 => CaptchaState::Exhausted,
+                            _ => CaptchaState::Pending
+                        };
+                        ok(&CaptchaStatus::new(id.to_string(), s, c.tries_left, c.expires_at))
+                    },
+                    None => error(error_code::NOT_FOUND, "not found")
+                }
+            },
+            ("POST", ["captcha", id, "redeem"]) => {
+                let redeemed = match state.captchas.get_mut(*id) {
+                    Some(c) if c.solved && !c.redeemed => {
+                        c.redeemed = true;
+                        true
+                    },
+                    _ => false
+                };
+                ok(&RedeemResponse::new(id.to_string(), redeemed))
+            },
+            _ => (404, String::from("Not Found"))
+        }
+    }
+
+    fn create(&self, state: &mut State, tries_left: usize, ttl: i64) -> (u16, String) {
+        state.created += 1;
+        let id = format!("00000000-0000-4000-8000-{:012x}", state.created);
+        state.captchas.insert(id.clone(), MockCaptcha {
+            solution: self.solution.clone(),
+            tries_left,
+            expires_at: now() + ttl,
+            solved: false,
+            redeemed: false,
+        });
+        ok(&NewCaptchaResponse { id, png: String::from(PNG), solution: self.solution.clone() })
+    }
+}
+
+fn valid_difficulty(d: &str) -> bool {
+    d == "easy" || d == "medium" || d == "hard"
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+fn ok<T: Serialize>(result: &T) -> (u16, String) {
+    let e = Envelope { error_code: error_code::PROCESSED, error_msg: String::from("processed"), result };
+    (200, serde_json::to_string(&e).unwrap_or_default())
+}
+
+fn error(code: u32, msg: &str) -> (u16, String) {
+    let e = Envelope { error_code: code, error_msg: msg.to_string(), result: "" };
+    (200, serde_json::to_string(&e).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_handle() {
+        let m = MockServer::with_solution("xyz");
+        let (status, body) = m.handle("POST", "/new/easy/0/60");
+        assert_eq!(status, 200);
+        assert!(body.contains("\"error_code\":2"));
+        assert_eq!(m.handle("DELETE", "/new/easy").0, 404);
+
+        let (_, body) = m.handle("GET", "/new/hard?seed=1");
+        assert!(body.contains("\"solution\":\"xyz\""));
+        assert!(m.handle("GET", "/captcha/unknown/status").1.contains("\"error_code\":5"));
+    }
+}
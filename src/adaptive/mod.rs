@@ -0,0 +1,170 @@
+use std::env;
+use std::net::IpAddr;
+use std::collections::HashMap;
+
+use crate::persistence::Persistence;
+use crate::pow;
+
+/// Name of the difficulty which selects the difficulty automatically.
+pub const AUTO: &str = "auto";
+
+const DEFAULT_WINDOW: usize = 600;
+const DEFAULT_RATE: f64 = 30.0;
+
+// Number of checked solutions required before the failure rate is taken into account.
+const MIN_ATTEMPTS: u64 = 5;
+
+/// Number of CAPTCHAs created, accepted and rejected within the current window.
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub struct Stats {
+    pub created: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+impl Stats {
+    fn from_counters(c: &HashMap<String, u64>) -> Stats {
+        let get = |k: &str| c.get(k).cloned().unwrap_or(0);
+        Stats {
+            created: get("created"),
+            accepted: get("accepted"),
+            rejected: get("rejected"),
+        }
+    }
+}
+
+/// Length of the window in seconds in which the statistics are collected. Configured via the
+/// environment variable `ADAPTIVE_WINDOW` (default: 600).
+pub fn window() -> usize {
+    env::var("ADAPTIVE_WINDOW").ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .filter(|w| *w > 0)
+        .unwrap_or(DEFAULT_WINDOW)
+}
+
+/// Number of CAPTCHAs per minute a client may create before it is considered suspicious.
+/// Configured via the environment variable `ADAPTIVE_RATE` (default: 30).
+pub fn rate() -> f64 {
+    env::var("ADAPTIVE_RATE").ok()
+        .and_then(|s| s.trim().parse::<f64>().ok())
+        .filter(|r| *r > 0.0)
+        .unwrap_or(DEFAULT_RATE)
+}
+
+/// Computes a score between 0 (normal) and 1 (suspicious) from the failure rate and the rate
+/// at which new CAPTCHAs are created.
+pub fn score(s: &Stats, window: usize, rate: f64) -> f64 {
+    let attempts = s.accepted + s.rejected;
+    let failures = match attempts {
+        n if n >= MIN_ATTEMPTS => s.rejected as f64 / n as f64,
+        _ => 0.0
+    };
+    let creations = (s.created as f64 / (window as f64 / 60.0) / rate).min(1.0);
+    // A client which creates CAPTCHAs at the maximum rate is escalated up to proof-of-work even
+    // if it never submits a solution.
+    (0.6 * failures + 0.4 * creations).max(0.75 * creations)
+}
+
+/// Maps a score to a difficulty.
+pub fn level(score: f64, clientid: &str, allow_pow: bool) -> String {
+    match score {
+        s if s < 0.25 => String::from("easy"),
+        s if s < 0.5  => String::from("medium"),
+        s if s < 0.75 || !allow_pow => String::from("hard"),
+        _ => format!("pow-{}", pow::bits_for(clientid))
+    }
+}
+
+/// Chooses a difficulty for the client from the statistics of its client id and of its IP.
+///
+/// If `allow_pow` is false, the hardest difficulty is `hard`. The statistics of the client id are
+/// only used if the client has sent a client id.
+pub async fn choose(clientid: Option<&str>, ip: Option<IpAddr>, allow_pow: bool) -> String {
+    let (w, r) = (window(), rate());
+    let mut worst: f64 = 0.0;
+    for s in subjects(clientid, ip) {
+        let score = Persistence::counters(&s).await
+            .map(|c| score(&Stats::from_counters(&c), w, r))
+            .unwrap_or_else(|e| {
+                error!("Could not get statistics for [{}] [{:?}].", s, e);
+                0.0
+            });
+        worst = worst.max(score);
+    }
+    let l = level(worst, clientid.unwrap_or(""), allow_pow);
+    info!("Adaptive difficulty for clientid [{}], ip [{:?}]: score [{:.2}], level [{}].",
+          clientid.unwrap_or("<unknown>"), ip, worst, l);
+    l
+}
+
+/// Records that `n` CAPTCHAs have been created with the difficulty `auto`.
+pub async fn record_created(clientid: Option<&str>, ip: Option<IpAddr>, n: u64) {
+    let w = window();
+    for s in subjects(clientid, ip) {
+        if let Err(e) = Persistence::count(&s, "created", n, w).await {
+            error!("Could not update statistics for [{}] [{:?}].", s, e);
+        }
+    }
+}
+
+/// Records that a solution has been checked. Only clients and IPs which have created CAPTCHAs
+/// with `auto` within the window have statistics, so solutions of other clients are not counted.
+pub async fn record_checked(clientid: Option<&str>, ip: Option<IpAddr>, accepted: bool) {
+    let field = if accepted { "accepted" } else { "rejected" };
+    for s in subjects(clientid, ip) {
+        if let Err(e) = Persistence::count_existing(&s, field, window()).await {
+            error!("Could not update statistics for [{}] [{:?}].", s, e);
+        }
+    }
+}
+
+fn subjects(clientid: Option<&str>, ip: Option<IpAddr>) -> Vec<String> {
+    let mut r = vec![];
+    if let Some(c) = clientid {
+        r.push(format!("client:{}", c));
+    }
+    if let Some(i) = ip {
+        r.push(format!("ip:{}", i));
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adaptive::{Stats, score, level};
+
+    fn stats(created: u64, accepted: u64, rejected: u64) -> Stats {
+        Stats { created, accepted, rejected }
+    }
+
+    #[test]
+    fn test_score() {
+        // No activity.
+        assert_eq!(score(&stats(0, 0, 0), 600, 30.0), 0.0);
+
+        // Normal usage: few CAPTCHAs, most of them solved.
+        assert!(score(&stats(20, 18, 2), 600, 30.0) < 0.25);
+
+        // Too few attempts to consider the failure rate.
+        assert!(score(&stats(4, 0, 4), 600, 30.0) < 0.25);
+
+        // Most of the solutions are wrong.
+        let s = score(&stats(20, 1, 19), 600, 30.0);
+        assert!((0.5..0.75).contains(&s));
+
+        // Many wrong solutions and many new CAPTCHAs.
+        assert!(score(&stats(600, 10, 300), 600, 30.0) >= 0.75);
+
+        // Many new CAPTCHAs without any solution.
+        assert!(score(&stats(3000, 0, 0), 600, 30.0) >= 0.75);
+    }
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(0.0, "c", true), "easy");
+        assert_eq!(level(0.3, "c", true), "medium");
+        assert_eq!(level(0.6, "c", true), "hard");
+        assert!(level(0.9, "c", true).starts_with("pow-"));
+        assert_eq!(level(0.9, "c", false), "hard");
+    }
+}
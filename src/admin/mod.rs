@@ -0,0 +1,145 @@
+use std::env;
+
+use crate::methods::{CaptchaError, persistence_error_mapping};
+use crate::persistence::{Persistence, Item};
+use crate::validation::{validate_id, validate_client, validate_count};
+
+/// Metadata of a persisted CAPTCHA. The solution is only included if it was requested.
+#[derive(Serialize, Debug)]
+pub struct CaptchaInfo {
+    id: String,
+    challenge: String,
+    client: String,
+    tries_left: usize,
+    expires: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    solution: Option<String>,
+}
+
+impl CaptchaInfo {
+    fn from_item(i: Item, reveal: bool) -> CaptchaInfo {
+        CaptchaInfo {
+            id: i.uuid(),
+            challenge: i.challenge(),
+            client: i.client(),
+            tries_left: i.tries_left(),
+            expires: i.expires(),
+            solution: if reveal { Some(i.solution()) } else { None },
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+/// A page of CAPTCHAs. `cursor` is used to request the next page and is 0 for the last page.
+#[derive(Serialize, Debug)]
+pub struct CaptchaList {
+    cursor: u64,
+    captchas: Vec<CaptchaInfo>,
+}
+
+impl CaptchaList {
+    pub fn len(&self) -> usize {
+        self.captchas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captchas.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PurgeResult {
+    purged: usize,
+}
+
+impl PurgeResult {
+    pub fn purged(&self) -> usize {
+        self.purged
+    }
+}
+
+/// Checks the token of an admin request against the environment variable `ADMIN_TOKEN`. If the
+/// variable is not set, all admin requests are rejected.
+pub fn authorized(token: &str) -> bool {
+    match env::var("ADMIN_TOKEN") {
+        Ok(ref t) if !t.is_empty() => constant_time_eq(t.as_bytes(), token.as_bytes()),
+        _ => false
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn captcha_inspect(id: String, reveal: bool) -> Result<CaptchaInfo, CaptchaError> {
+    let i = validate_id(id)?;
+    Persistence::get(i.to_hyphenated().to_string()).await
+        .map(|item| CaptchaInfo::from_item(item, reveal))
+        .map_err(persistence_error_mapping)
+}
+
+pub async fn captcha_revoke(id: String) -> Result<CaptchaInfo, CaptchaError> {
+    let i = validate_id(id)?;
+    Persistence::remove(i.to_hyphenated().to_string()).await
+        .map(|item| CaptchaInfo::from_item(item, false))
+        .map_err(persistence_error_mapping)
+}
+
+pub async fn captcha_list(client: Option<String>, cursor: Option<u64>, count: Option<usize>) -> Result<CaptchaList, CaptchaError> {
+    let c = match client {
+        Some(c) => Some(validate_client(c)?),
+        None    => None
+    };
+    let n = validate_count(count)?;
+    let (next, items) = Persistence::list(c.as_deref(), cursor.unwrap_or(0), n).await
+        .map_err(persistence_error_mapping)?;
+    Ok(CaptchaList {
+        cursor: next,
+        captchas: items.into_iter().map(|i| CaptchaInfo::from_item(i, false)).collect(),
+    })
+}
+
+pub async fn captcha_purge(client: String) -> Result<PurgeResult, CaptchaError> {
+    let c = validate_client(client)?;
+    Persistence::purge(&c).await
+        .map(|purged| PurgeResult { purged })
+        .map_err(persistence_error_mapping)
+}
+
+/// Removes all CAPTCHAs of all clients.
+pub async fn captcha_purge_all() -> Result<PurgeResult, CaptchaError> {
+    Persistence::purge_all().await
+        .map(|purged| PurgeResult { purged })
+        .map_err(persistence_error_mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use crate::admin::{authorized, constant_time_eq};
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+    }
+
+    #[test]
+    fn test_authorized() {
+        env::remove_var("ADMIN_TOKEN");
+        assert!(!authorized(""));
+
+        env::set_var("ADMIN_TOKEN", "");
+        assert!(!authorized(""));
+
+        env::set_var("ADMIN_TOKEN", "secret");
+        assert!(authorized("secret"));
+        assert!(!authorized("other"));
+
+        env::remove_var("ADMIN_TOKEN");
+    }
+}
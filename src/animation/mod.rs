@@ -0,0 +1,152 @@
+//! Animated CAPTCHAs. Each frame of the animation shows only some of the characters and noise
+//! which moves from frame to frame. Hence the solution cannot be read from a single frame, but a
+//! human can read it over time. Each frame is distorted by the filters of the difficulty anew.
+//! The animation is encoded as GIF.
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, Rgb, RgbImage};
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::filters::Profile;
+use crate::theme::Theme;
+
+/// The characters are split into this number of groups. Each frame shows one group.
+const GROUPS: usize = 3;
+
+/// Number of frames. Each group is shown twice.
+pub const FRAMES: usize = 2 * GROUPS;
+
+/// Time a frame is shown in milliseconds.
+const DELAY_MS: u32 = 400;
+
+/// Number of moving dots.
+const PARTICLES: usize = 40;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// A dot of noise which moves by `(dx, dy)` per frame.
+struct Particle {
+    x: f32,
+    y: f32,
+    dx: f32,
+    dy: f32,
+    size: u32,
+}
+
+/// Creates an animated CAPTCHA from an image with `chars` characters. The image must have black
+/// characters on a white background. The filters of the profile and the colors of the theme are
+/// applied to each frame.
+pub fn animate(img: &RgbImage, chars: usize, profile: &Profile, theme: &Theme, rng: &mut StdRng) -> Option<Vec<u8>> {
+    let (w, h) = img.dimensions();
+
+    // The characters have about the same width, hence the columns with ink are split into
+    // `chars` slots of equal width, one for each character.
+    let columns: Vec<u32> = (0..w).filter(|&x| (0..h).any(|y| ink(img.get_pixel(x, y)))).collect();
+    let (x0, x1) = (*columns.first()?, *columns.last()? + 1);
+    let slot = (x1 - x0) as f32 / chars.max(1) as f32;
+    // Neighbouring characters are in different groups; the groups start at a random offset.
+    let offset = rng.gen_range(0..GROUPS);
+    let group = |x: u32| ((((x - x0) as f32 / slot) as usize).min(chars.max(1) - 1) + offset) % GROUPS;
+
+    let mut particles: Vec<Particle> = (0..PARTICLES)
+        .map(|_| Particle {
+            x: rng.gen_range(0.0..w as f32),
+            y: rng.gen_range(0.0..h as f32),
+            dx: rng.gen_range(-6.0..=6.0),
+            dy: rng.gen_range(-3.0..=3.0),
+            size: rng.gen_range(2..=4),
+        })
+        .collect();
+
+    let mut frames = Vec::with_capacity(FRAMES);
+    for f in 0..FRAMES {
+        let mut frame = img.clone();
+        for x in x0..x1 {
+            if group(x) != f % GROUPS {
+                for y in 0..h {
+                    frame.put_pixel(x, y, WHITE);
+                }
+            }
+        }
+        for p in particles.iter_mut() {
+            draw(&mut frame, p);
+            p.x = (p.x + p.dx).rem_euclid(w as f32);
+            p.y = (p.y + p.dy).rem_euclid(h as f32);
+        }
+        profile.apply(&mut frame, theme, rng);
+        let rgba = DynamicImage::ImageRgb8(frame).to_rgba8();
+        frames.push(Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(DELAY_MS, 1)));
+    }
+    encode_gif(frames)
+}
+
+fn ink(p: &Rgb<u8>) -> bool {
+    p.0[0] < 128
+}
+
+fn draw(img: &mut RgbImage, p: &Particle) {
+    for dy in 0..p.size {
+        for dx in 0..p.size {
+            let (x, y) = (p.x as u32 + dx, p.y as u32 + dy);
+            if x < img.width() && y < img.height() {
+                img.put_pixel(x, y, BLACK);
+            }
+        }
+    }
+}
+
+fn encode_gif(frames: Vec<Frame>) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    {
+        // Speed 10 is the default of the gif crate. Lower values are much slower.
+        let mut e = GifEncoder::new_with_speed(&mut out, 10);
+        e.set_repeat(Repeat::Infinite).ok()?;
+        e.encode_frames(frames).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::AnimationDecoder;
+    use image::codecs::gif::GifDecoder;
+    use image::{Rgb, RgbImage};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::animation::{animate, FRAMES};
+    use crate::filters::Profile;
+    use crate::theme::Theme;
+
+    /// Four black blocks, one for each character, on a white background.
+    fn image() -> RgbImage {
+        let mut img = RgbImage::from_pixel(220, 120, Rgb([255, 255, 255]));
+        for y in 40..80 {
+            for x in 40..180 {
+                if (x - 40) % 35 < 25 {
+                    img.put_pixel(x, y, Rgb([0, 0, 0]));
+                }
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_animate() {
+        let animation = |p: &Profile| animate(&image(), 4, p, &Theme::default(), &mut StdRng::seed_from_u64(3)).unwrap();
+        let a = animation(&Profile::default());
+        assert_eq!(&a[0..6], b"GIF89a");
+        assert_eq!(a, animation(&Profile::default()));
+        assert_eq!(animation(&Profile::builtin("hard")), animation(&Profile::builtin("hard")));
+
+        let frames = GifDecoder::new(&a[..]).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), FRAMES);
+        // Each frame shows only some of the characters.
+        let dark = |x: u32| frames.iter().filter(|f| f.buffer().get_pixel(x, 60).0[0] < 128).count();
+        for x in &[50, 85, 120, 155] {
+            assert!(dark(*x) > 0 && dark(*x) < FRAMES, "{}", x);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Audio challenges for visitors who cannot see the image of a CAPTCHA.
+//!
+//! The characters of the solution are read out one after another with the recordings of the
+//! `captcha` crate. The crate adds noise to each recording. As the recordings are the same for
+//! each character, the volume of each character and the pauses between them are chosen at random
+//! so that the characters cannot be cut out at fixed offsets. Audio challenges are nevertheless
+//! easier to solve automatically than the images.
+
+use std::io::Cursor;
+
+use captcha::RngCaptcha;
+use hound::{WavReader, WavSpec, WavWriter};
+use rand::Rng;
+use rand::rngs::StdRng;
+
+/// Format of the recordings of the `captcha` crate.
+const SPEC: WavSpec = WavSpec {
+    channels: 1,
+    sample_rate: 22050,
+    bits_per_sample: 16,
+    sample_format: hound::SampleFormat::Int,
+};
+
+/// Range of the length of the pauses before, between and after the characters in samples.
+const MIN_PAUSE: u32 = 4000;
+const MAX_PAUSE: u32 = 12000;
+
+/// Amplitude of the noise in the pauses, the same as the noise of the recordings.
+const NOISE: i16 = 3000;
+
+/// Range of the volume of a character relative to its recording.
+const MIN_GAIN: f32 = 0.6;
+const MAX_GAIN: f32 = 1.0;
+
+/// Returns an audio in WAV format which reads out `text` or `None` if a character cannot be read
+/// out.
+pub fn wav(text: &str, rng: &mut StdRng) -> Option<Vec<u8>> {
+    let mut cursor = Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut cursor, SPEC).ok()?;
+        pause(&mut writer, rng)?;
+        for c in text.chars() {
+            let recording = RngCaptcha::from_rng(&mut *rng)
+                .set_chars(&[c])
+                .add_chars(1)
+                .as_wav()
+                .pop()??;
+            let gain = rng.gen_range(MIN_GAIN..MAX_GAIN);
+            for s in WavReader::new(&recording[..]).ok()?.samples::<i16>() {
+                writer.write_sample((s.ok()? as f32 * gain) as i16).ok()?;
+            }
+            pause(&mut writer, rng)?;
+        }
+        writer.finalize().ok()?;
+    }
+    Some(cursor.into_inner())
+}
+
+fn pause<W: std::io::Write + std::io::Seek>(writer: &mut WavWriter<W>, rng: &mut StdRng) -> Option<()> {
+    for _ in 0..rng.gen_range(MIN_PAUSE..MAX_PAUSE) {
+        writer.write_sample(rng.gen_range(-NOISE..NOISE)).ok()?;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::WavReader;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::audio::{wav, MAX_PAUSE, SPEC};
+
+    #[test]
+    fn test_wav() {
+        let data = wav("a4K", &mut StdRng::seed_from_u64(1)).unwrap();
+        let reader = WavReader::new(&data[..]).unwrap();
+        assert_eq!(reader.spec(), SPEC);
+        // Each recording has 36000 samples.
+        assert!(reader.len() > 3 * 36000 && reader.len() < 3 * 36000 + 4 * MAX_PAUSE);
+
+        // The pauses differ.
+        assert_ne!(wav("a4K", &mut StdRng::seed_from_u64(2)).unwrap().len(), data.len());
+        assert_eq!(wav("-", &mut StdRng::seed_from_u64(1)), None);
+    }
+}
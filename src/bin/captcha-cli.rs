@@ -0,0 +1,262 @@
+extern crate env_logger;
+extern crate rust_captcha;
+extern crate serde_json;
+extern crate tokio;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use rust_captcha::admin::{captcha_inspect, captcha_purge, captcha_purge_all};
+use rust_captcha::methods::{CaptchaBatch, CaptchaError, captcha_benchmark, captcha_generate, captcha_solution};
+use serde_json::json;
+
+const USAGE: &str = "Usage:
+    captcha-cli generate [--difficulty <easy|medium|hard>] [--count <n>] [--out <dir>] [--format <csv|json>] [--seed <n>]
+    captcha-cli verify <id> <solution>
+    captcha-cli inspect [--reveal] <id>
+    captcha-cli purge (--client <client> | --all)
+    captcha-cli bench [--difficulty <easy|medium|hard>] [--count <n>] [--seed <n>] [--format <text|json>] [--max-rate <percent>]
+
+The commands verify, inspect and purge require the environment variable REDIS_HOST.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(msg: &str, e: CaptchaError) -> ! {
+    eprintln!("{} [{:?}]", msg, e);
+    process::exit(1);
+}
+
+/// Options of the form `(name, value)`. The value of a flag is empty.
+type Options = Vec<(String, String)>;
+
+/// Splits the arguments into options of the form `--name value` or `--flag` and positional
+/// arguments. Returns `None` if an option is neither in `options` nor in `flags` or if the value
+/// of an option is missing.
+fn parse(args: &[String], options: &[&str], flags: &[&str]) -> Option<(Options, Vec<String>)> {
+    let mut opts = vec![];
+    let mut pos = vec![];
+    let mut i = 0;
+    while i < args.len() {
+        let a = &args[i];
+        if let Some(name) = a.strip_prefix("--") {
+            if flags.contains(&name) {
+                opts.push((name.to_string(), String::new()));
+            } else if options.contains(&name) {
+                opts.push((name.to_string(), args.get(i + 1)?.clone()));
+                i += 1;
+            } else {
+                return None;
+            }
+        } else {
+            pos.push(a.clone());
+        }
+        i += 1;
+    }
+    Some((opts, pos))
+}
+
+fn opt(opts: &[(String, String)], name: &str) -> Option<String> {
+    opts.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone())
+}
+
+fn write_solutions(batch: &CaptchaBatch, out: &Path, format: &str) -> std::io::Result<()> {
+    match format {
+        "json" => {
+            let entries = batch.captchas().iter()
+                .map(|c| json!({
+                    "id": c.uuid(),
+                    "solution": c.solution(),
+                    "file": format!("{}.png", c.uuid())
+                }))
+                .collect::<Vec<_>>();
+            let mut f = File::create(out.join("solutions.json"))?;
+            f.write_all(serde_json::to_string_pretty(&entries)?.as_bytes())
+        },
+        _ => {
+            let mut f = File::create(out.join("solutions.csv"))?;
+            writeln!(f, "id,solution,file")?;
+            for c in batch.captchas() {
+                writeln!(f, "{},{},{}.png", c.uuid(), c.solution(), c.uuid())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn generate(args: &[String]) {
+    let (opts, pos) = parse(args, &["difficulty", "count", "out", "format", "seed"], &[])
+        .unwrap_or_else(|| usage());
+    if !pos.is_empty() {
+        usage();
+    }
+    let difficulty = opt(&opts, "difficulty").unwrap_or_else(|| String::from("medium"));
+    let count = opt(&opts, "count").unwrap_or_else(|| String::from("1"))
+        .parse::<usize>().unwrap_or_else(|_| usage());
+    let out = PathBuf::from(opt(&opts, "out").unwrap_or_else(|| String::from(".")));
+    let format = opt(&opts, "format").unwrap_or_else(|| String::from("csv"));
+    if format != "csv" && format != "json" {
+        usage();
+    }
+    let seed = opt(&opts, "seed").map(|s| s.parse::<u64>().unwrap_or_else(|_| usage()));
+
+    let batch = captcha_generate(difficulty, count, seed).await
+        .unwrap_or_else(|e| fail("Failed to generate CAPTCHAs.", e));
+
+    let written = fs::create_dir_all(&out)
+        .and_then(|_| batch.captchas().iter()
+            .map(|c| File::create(out.join(format!("{}.png", c.uuid()))).and_then(|mut f| f.write_all(c.png())))
+            .collect::<std::io::Result<Vec<_>>>())
+        .and_then(|_| write_solutions(&batch, &out, &format));
+    if let Err(e) = written {
+        eprintln!("Failed to write CAPTCHAs to [{}] [{}]", out.display(), e);
+        process::exit(1);
+    }
+    println!("Generated {} CAPTCHAs in [{}].", batch.len(), out.display());
+}
+
+async fn verify(args: &[String]) {
+    if args.len() != 2 {
+        usage();
+    }
+    let details = captcha_solution(args[0].clone(), args[1].clone(), None).await
+        .unwrap_or_else(|e| fail("Failed to check solution.", e));
+    println!("{}", serde_json::to_string(&details.response()).expect("serializing result"));
+    if !details.accepted() {
+        process::exit(1);
+    }
+}
+
+async fn inspect(args: &[String]) {
+    let (opts, pos) = parse(args, &[], &["reveal"]).unwrap_or_else(|| usage());
+    if pos.len() != 1 {
+        usage();
+    }
+    let info = captcha_inspect(pos[0].clone(), opt(&opts, "reveal").is_some()).await
+        .unwrap_or_else(|e| fail("Failed to inspect CAPTCHA.", e));
+    println!("{}", serde_json::to_string_pretty(&info).expect("serializing CAPTCHA"));
+}
+
+async fn purge(args: &[String]) {
+    let (opts, pos) = parse(args, &["client"], &["all"]).unwrap_or_else(|| usage());
+    if !pos.is_empty() {
+        usage();
+    }
+    let r = match (opt(&opts, "client"), opt(&opts, "all")) {
+        (Some(c), None) => captcha_purge(c).await,
+        (None, Some(_)) => captcha_purge_all().await,
+        _ => usage()
+    };
+    let p = r.unwrap_or_else(|e| fail("Failed to purge CAPTCHAs.", e));
+    println!("Purged {} CAPTCHAs.", p.purged());
+}
+
+/// Solves CAPTCHAs with the baseline OCR solver and prints the solve rates. Exits with 1 if the
+/// solve rate of a difficulty with all its filters exceeds `--max-rate`.
+async fn bench(args: &[String]) {
+    let (opts, pos) = parse(args, &["difficulty", "count", "seed", "format", "max-rate"], &[])
+        .unwrap_or_else(|| usage());
+    if !pos.is_empty() {
+        usage();
+    }
+    let difficulties = match opt(&opts, "difficulty") {
+        Some(d) => vec![d],
+        None => vec![String::from("easy"), String::from("medium"), String::from("hard")]
+    };
+    let count = opt(&opts, "count").unwrap_or_else(|| String::from("1000"))
+        .parse::<usize>().unwrap_or_else(|_| usage());
+    let seed = opt(&opts, "seed").map_or(0, |s| s.parse::<u64>().unwrap_or_else(|_| usage()));
+    let format = opt(&opts, "format").unwrap_or_else(|| String::from("text"));
+    if format != "text" && format != "json" {
+        usage();
+    }
+    let max_rate = opt(&opts, "max-rate").map(|s| s.parse::<f64>().unwrap_or_else(|_| usage()));
+
+    let scores = captcha_benchmark(difficulties, count, seed).await
+        .unwrap_or_else(|e| fail("Failed to run benchmark.", e));
+
+    if format == "json" {
+        let entries = scores.iter()
+            .map(|s| json!({
+                "difficulty": s.difficulty,
+                "filters": s.filters,
+                "count": s.count,
+                "solved": s.solved,
+                "solve_rate": s.solve_rate(),
+                "char_rate": s.char_rate()
+            }))
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&entries).expect("serializing scores"));
+    } else {
+        println!("{:<10} {:<10} {:>12} {:>8} {:>8}", "difficulty", "filters", "solved", "rate", "chars");
+        for s in &scores {
+            println!("{:<10} {:<10} {:>12} {:>7.1}% {:>7.1}%", s.difficulty, s.filters,
+                format!("{}/{}", s.solved, s.count), 100.0 * s.solve_rate(), 100.0 * s.char_rate());
+        }
+    }
+
+    if let Some(max) = max_rate {
+        let exceeded = scores.iter()
+            .filter(|s| s.filters == "all" && 100.0 * s.solve_rate() > max)
+            .map(|s| s.difficulty.clone())
+            .collect::<Vec<_>>();
+        if !exceeded.is_empty() {
+            eprintln!("Solve rate exceeds {}% for [{}].", max, exceeded.join(", "));
+            process::exit(1);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("generate") => generate(&args[1..]).await,
+        Some("verify")   => verify(&args[1..]).await,
+        Some("inspect")  => inspect(&args[1..]).await,
+        Some("purge")    => purge(&args[1..]).await,
+        Some("bench")    => bench(&args[1..]).await,
+        _ => usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{opt, parse};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let (opts, pos) = parse(&args("--reveal 04f4 --count 3 x"), &["count"], &["reveal"]).unwrap();
+        assert_eq!(opts, vec![(String::from("reveal"), String::new()), (String::from("count"), String::from("3"))]);
+        assert_eq!(pos, args("04f4 x"));
+
+        // The value of an option may start with a dash.
+        let (opts, pos) = parse(&args("--seed -1"), &["seed"], &[]).unwrap();
+        assert_eq!(opt(&opts, "seed"), Some(String::from("-1")));
+        assert!(pos.is_empty());
+
+        // Unknown options and missing values are rejected.
+        assert_eq!(parse(&args("--cout 50"), &["count"], &[]), None);
+        assert_eq!(parse(&args("--al"), &["client"], &["all"]), None);
+        assert_eq!(parse(&args("--count"), &["count"], &[]), None);
+    }
+
+    #[test]
+    fn test_opt() {
+        let (opts, _) = parse(&args("--format json --format csv --all"), &["format"], &["all"]).unwrap();
+        assert_eq!(opt(&opts, "format"), Some(String::from("json")));
+        assert_eq!(opt(&opts, "all"), Some(String::new()));
+        assert_eq!(opt(&opts, "count"), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::env;
+
+use crate::sites;
+
+/// Methods which browsers may use to call the service.
+pub const ALLOWED_METHODS: &str = "GET, POST, OPTIONS";
+
+/// Headers which browsers may send to the service.
+pub const ALLOWED_HEADERS: &str = "Content-Type, X-Client-ID";
+
+const DEFAULT_MAX_AGE: u32 = 600;
+
+/// Returns the origins which may call the service for all clients. Configured via the
+/// environment variable `CORS_ORIGINS` which contains a comma separated list of origins. The
+/// origin `*` allows all origins. If the variable is not set, no origin is allowed.
+pub fn origins() -> Vec<String> {
+    env::var("CORS_ORIGINS")
+        .map(|s| s.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Returns the origins which may call the service for the client `clientid`.
+///
+/// The origins of a registered site are taken from the site registry. Otherwise they are read
+/// from the environment variable `CORS_ORIGINS_CLIENTS` which contains a comma separated list of
+/// `<clientid>=<origin> <origin> ...` entries. If the client is not in the list, the origins of
+/// `CORS_ORIGINS` are used.
+pub fn origins_for(clientid: Option<&str>) -> Vec<String> {
+    if let Some(site) = clientid.and_then(sites::get) {
+        return site.allowed_origins().to_vec();
+    }
+    let clients = env::var("CORS_ORIGINS_CLIENTS").unwrap_or_default();
+    clientid
+        .and_then(|c| client_origins(&clients, c))
+        .unwrap_or_else(origins)
+}
+
+fn client_origins(clients: &str, clientid: &str) -> Option<Vec<String>> {
+    clients.split(',')
+        .filter_map(|entry| entry.split_once('=').map(|(c, o)| (c.trim(), o)))
+        .find(|&(c, _)| c == clientid)
+        .map(|(_, o)| o.split_whitespace().map(|s| s.to_string()).collect())
+}
+
+/// Returns all origins which may call the service for any client. A preflight request does not
+/// contain the client id, hence it is checked against these origins.
+pub fn all_origins() -> Vec<String> {
+    let clients = env::var("CORS_ORIGINS_CLIENTS").unwrap_or_default();
+    let mut r = origins();
+    r.extend(clients.split(',')
+        .filter_map(|entry| entry.split_once('=').map(|(_, o)| o))
+        .flat_map(|o| o.split_whitespace().map(|s| s.to_string())));
+    r.extend(sites::all().iter().flat_map(|s| s.allowed_origins().to_vec()));
+    r
+}
+
+/// Returns the value of the header `Access-Control-Allow-Origin` for a request from `origin` or
+/// `None` if the origin is not allowed.
+pub fn allow_origin(origin: &str, allowed: &[String]) -> Option<String> {
+    if allowed.iter().any(|o| o == origin) {
+        Some(origin.to_string())
+    } else if allowed.iter().any(|o| o == "*") {
+        Some(String::from("*"))
+    } else {
+        None
+    }
+}
+
+/// Number of seconds a browser may cache the result of a preflight request. Configured via the
+/// environment variable `CORS_MAX_AGE` (default: 600).
+pub fn max_age() -> u32 {
+    env::var("CORS_MAX_AGE").ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(DEFAULT_MAX_AGE)
+}
+
+/// Security headers for a response with the given content type.
+///
+/// API responses must not be rendered or framed at all. The widget is loaded by other sites
+/// and therefore may be embedded cross-origin.
+pub fn security_headers(content_type: Option<&str>) -> Vec<(&'static str, &'static str)> {
+    let embeddable = match content_type {
+        Some(t) => t.starts_with("application/javascript") || t.starts_with("text/css") || t.starts_with("audio/"),
+        None    => false
+    };
+    let mut r = vec![
+        ("X-Content-Type-Options", "nosniff"),
+        ("Referrer-Policy", "no-referrer"),
+        ("Content-Security-Policy", "default-src 'none'; frame-ancestors 'none'"),
+    ];
+    if embeddable {
+        r.push(("Cross-Origin-Resource-Policy", "cross-origin"));
+    } else {
+        r.push(("X-Frame-Options", "DENY"));
+        r.push(("Cross-Origin-Resource-Policy", "same-site"));
+        r.push(("Cache-Control", "no-store"));
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cors::{client_origins, allow_origin, security_headers};
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_client_origins() {
+        let clients = "shop=https://shop.example https://www.shop.example, blog = https://blog.example";
+        assert_eq!(client_origins(clients, "shop"), Some(strings(&["https://shop.example", "https://www.shop.example"])));
+        assert_eq!(client_origins(clients, "blog"), Some(strings(&["https://blog.example"])));
+        assert_eq!(client_origins(clients, "other"), None);
+        assert_eq!(client_origins("", "shop"), None);
+    }
+
+    #[test]
+    fn test_allow_origin() {
+        let allowed = strings(&["https://a.example"]);
+        assert_eq!(allow_origin("https://a.example", &allowed), Some(String::from("https://a.example")));
+        assert_eq!(allow_origin("https://b.example", &allowed), None);
+        assert_eq!(allow_origin("https://b.example", &strings(&["*"])), Some(String::from("*")));
+        assert_eq!(allow_origin("https://b.example", &[]), None);
+    }
+
+    #[test]
+    fn test_security_headers() {
+        let api = security_headers(Some("application/json"));
+        assert!(api.contains(&("X-Frame-Options", "DENY")));
+        let js = security_headers(Some("application/javascript"));
+        assert!(js.contains(&("Cross-Origin-Resource-Policy", "cross-origin")));
+        assert!(!js.iter().any(|&(h, _)| h == "X-Frame-Options"));
+        assert!(security_headers(Some("audio/wav")).contains(&("Cross-Origin-Resource-Policy", "cross-origin")));
+    }
+}
@@ -0,0 +1,618 @@
+//! Filters which distort image CAPTCHAs after they have been created by the `captcha` crate.
+//!
+//! Each difficulty has a profile which selects the filters and their strength. The built-in
+//! profiles can be replaced with the JSON file in the environment variable `FILTERS_FILE`:
+//!
+//! ```json
+//! {
+//!   "hard": {
+//!     "jitter": { "degrees": 15 },
+//!     "speckle": { "density": 0.5 },
+//!     "grid": { "gap": 4 },
+//!     "wave": { "amplitude": 20, "period": 200 },
+//!     "warp": { "amplitude": 4, "period": 40, "elastic": 2.5 },
+//!     "occlusion": { "lines": 3, "arcs": 2, "thickness": 2 },
+//!     "dots": { "count": 20, "min_radius": 5, "max_radius": 7 },
+//!     "clutter": { "count": 8, "shade": 170 },
+//!     "noise": { "amount": 40, "density": 0.3 }
+//!   }
+//! }
+//! ```
+//!
+//! Speckle, grid, wave and dots are the filters of the `captcha` crate. They are implemented
+//! here because the filters of the crate take their randomness from the thread, whereas these
+//! filters take it from the generator of the CAPTCHA. Hence a seeded CAPTCHA still depends on
+//! the seed only.
+
+use std::collections::HashMap;
+use std::env;
+use std::f32::consts::PI;
+use std::fs;
+use std::sync::RwLock;
+
+use image::{Rgb, RgbImage};
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::theme::Theme;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+const DIFFICULTIES: [&str; 3] = ["easy", "medium", "hard"];
+
+/// Profiles from `FILTERS_FILE`, by difficulty.
+static PROFILES: RwLock<Vec<(String, Profile)>> = RwLock::new(Vec::new());
+
+/// Moves the pixels along sine waves and a random displacement field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Warp {
+    /// Amplitude of the sine waves in pixels.
+    pub amplitude: f32,
+    /// Period of the sine waves in pixels.
+    pub period: f32,
+    /// Maximum random displacement in pixels.
+    #[serde(default)]
+    pub elastic: f32,
+}
+
+/// Draws random lines and arcs over the characters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Occlusion {
+    #[serde(default)]
+    pub lines: u32,
+    #[serde(default)]
+    pub arcs: u32,
+    /// Thickness in pixels.
+    pub thickness: u32,
+}
+
+/// Draws small copies of the characters into the background.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clutter {
+    pub count: u32,
+    /// Gray value of the characters, 0 (black) to 255 (white).
+    pub shade: u8,
+}
+
+/// Sets random pixels to black.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Speckle {
+    /// Fraction of the pixels which are set to black.
+    pub density: f32,
+}
+
+/// Draws black lines every `gap` pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub gap: u32,
+}
+
+/// Moves the columns up and down along a sine wave.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Wave {
+    /// Amplitude in pixels.
+    pub amplitude: f32,
+    /// Period in pixels.
+    pub period: f32,
+}
+
+/// Draws black dots of random size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Dots {
+    pub count: u32,
+    pub min_radius: u32,
+    pub max_radius: u32,
+}
+
+/// Rotates each character by a random angle.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Jitter {
+    /// Maximum angle in degrees.
+    pub degrees: f32,
+}
+
+/// Changes the colors of random pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Noise {
+    /// Maximum change of a color channel.
+    pub amount: u8,
+    /// Fraction of the pixels which are changed.
+    pub density: f32,
+}
+
+/// The filters of a difficulty. The filters are applied in the order of the fields.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    #[serde(default)]
+    pub jitter: Option<Jitter>,
+    #[serde(default)]
+    pub speckle: Option<Speckle>,
+    #[serde(default)]
+    pub grid: Option<Grid>,
+    #[serde(default)]
+    pub wave: Option<Wave>,
+    #[serde(default)]
+    pub warp: Option<Warp>,
+    #[serde(default)]
+    pub dots: Option<Dots>,
+    #[serde(default)]
+    pub occlusion: Option<Occlusion>,
+    #[serde(default)]
+    pub clutter: Option<Clutter>,
+    #[serde(default)]
+    pub noise: Option<Noise>,
+}
+
+impl Profile {
+    /// Returns the built-in profile of a difficulty. Speckle, grid, wave and dots have the
+    /// strength of the `amelia` CAPTCHAs of the `captcha` crate.
+    pub fn builtin(difficulty: &str) -> Profile {
+        let (degrees, speckle, gap, wave, dots, min_radius) = match difficulty {
+            "easy"   => (5.0, 0.2, 8, 8.0, 10, 3),
+            "medium" => (10.0, 0.3, 6, 14.0, 15, 4),
+            _        => (15.0, 0.5, 4, 20.0, 20, 5),
+        };
+        let (amplitude, period, elastic, lines, arcs, clutter, amount, density) = match difficulty {
+            "easy"   => (2.0, 60.0, 0.0, 1, 0, 0, 20, 0.1),
+            "medium" => (3.0, 50.0, 1.5, 2, 1, 4, 30, 0.2),
+            _        => (4.0, 40.0, 2.5, 3, 2, 8, 40, 0.3),
+        };
+        Profile {
+            jitter: Some(Jitter { degrees }),
+            speckle: Some(Speckle { density: speckle }),
+            grid: Some(Grid { gap }),
+            wave: Some(Wave { amplitude: wave, period: 200.0 }),
+            warp: Some(Warp { amplitude, period, elastic }),
+            dots: Some(Dots { count: dots, min_radius, max_radius: 7 }),
+            occlusion: Some(Occlusion { lines, arcs, thickness: 2 }),
+            clutter: if clutter > 0 { Some(Clutter { count: clutter, shade: 170 }) } else { None },
+            noise: Some(Noise { amount, density }),
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if let Some(ref w) = self.warp {
+            if w.period <= 0.0 || w.amplitude < 0.0 || w.elastic < 0.0 {
+                return Err(String::from("invalid warp"));
+            }
+        }
+        if let Some(ref s) = self.speckle {
+            if !(0.0..=1.0).contains(&s.density) {
+                return Err(String::from("invalid speckle density"));
+            }
+        }
+        if let Some(ref g) = self.grid {
+            if g.gap < 2 {
+                return Err(String::from("invalid grid gap"));
+            }
+        }
+        if let Some(ref w) = self.wave {
+            if w.period <= 0.0 || w.amplitude < 0.0 {
+                return Err(String::from("invalid wave"));
+            }
+        }
+        if let Some(ref d) = self.dots {
+            if d.min_radius > d.max_radius || d.max_radius > 20 {
+                return Err(String::from("invalid dots"));
+            }
+        }
+        if let Some(ref o) = self.occlusion {
+            if o.thickness == 0 || o.thickness > 10 {
+                return Err(String::from("invalid thickness"));
+            }
+        }
+        if let Some(ref j) = self.jitter {
+            if !(0.0..=45.0).contains(&j.degrees) {
+                return Err(String::from("invalid jitter"));
+            }
+        }
+        if let Some(ref n) = self.noise {
+            if !(0.0..=1.0).contains(&n.density) {
+                return Err(String::from("invalid noise density"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the filters and the colors of the theme to an image CAPTCHA with black characters
+    /// on a white background.
+    pub fn apply(&self, img: &mut RgbImage, theme: &Theme, rng: &mut StdRng) {
+        let source = img.clone();
+        if let Some(ref f) = self.jitter {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.speckle {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.grid {
+            f.apply(img);
+        }
+        if let Some(ref f) = self.wave {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.warp {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.dots {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.occlusion {
+            f.apply(img, rng);
+        }
+        if let Some(ref f) = self.clutter {
+            f.apply(img, &source, rng);
+        }
+        theme.colorize(img);
+        // Noise is added after the colors so that it is colored, too.
+        if let Some(ref f) = self.noise {
+            f.apply(img, rng);
+        }
+    }
+}
+
+/// Parses the profiles of a filter file and checks them.
+pub fn parse(json: &str) -> Result<Vec<(String, Profile)>, String> {
+    let profiles: HashMap<String, Profile> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut r = vec![];
+    for (d, p) in profiles {
+        if !DIFFICULTIES.contains(&d.as_str()) {
+            return Err(format!("invalid difficulty [{}]", d));
+        }
+        p.check().map_err(|e| format!("{} for [{}]", e, d))?;
+        r.push((d, p));
+    }
+    Ok(r)
+}
+
+/// Loads the profiles from the file in the environment variable `FILTERS_FILE`. Returns the
+/// number of profiles. If the variable is not set, the built-in profiles are used.
+pub fn load() -> Result<usize, String> {
+    let path = match env::var("FILTERS_FILE") {
+        Ok(p) => p,
+        Err(_) => return Ok(0)
+    };
+    let json = fs::read_to_string(&path).map_err(|e| format!("cannot read [{}] [{}]", path, e))?;
+    let profiles = parse(&json)?;
+    let n = profiles.len();
+    set(profiles);
+    Ok(n)
+}
+
+/// Replaces the configured profiles.
+pub fn set(profiles: Vec<(String, Profile)>) {
+    *PROFILES.write().unwrap_or_else(|e| e.into_inner()) = profiles;
+}
+
+/// Returns the profile of a difficulty.
+pub fn profile(difficulty: &str) -> Profile {
+    PROFILES.read().unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(d, _)| d == difficulty)
+        .map(|(_, p)| p.clone())
+        .unwrap_or_else(|| Profile::builtin(difficulty))
+}
+
+fn ink(p: &Rgb<u8>) -> bool {
+    p.0[0] < 128
+}
+
+impl Jitter {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        for (x0, x1) in segments(img) {
+            let angle = rng.gen_range(-self.degrees..=self.degrees).to_radians();
+            rotate(img, x0, x1, angle);
+        }
+    }
+}
+
+/// Returns the ranges of columns which contain ink, i.e. the characters or groups of touching
+/// characters.
+fn segments(img: &RgbImage) -> Vec<(u32, u32)> {
+    let (w, h) = img.dimensions();
+    let mut r = vec![];
+    let mut start = None;
+    for x in 0..w {
+        let has_ink = (0..h).any(|y| ink(img.get_pixel(x, y)));
+        match (has_ink, start) {
+            (true, None) => start = Some(x),
+            (false, Some(s)) => {
+                r.push((s, x));
+                start = None;
+            },
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        r.push((s, w));
+    }
+    r
+}
+
+/// Rotates the ink in the columns `x0..x1` around its center.
+fn rotate(img: &mut RgbImage, x0: u32, x1: u32, angle: f32) {
+    let (w, h) = img.dimensions();
+    let rows: Vec<u32> = (0..h).filter(|&y| (x0..x1).any(|x| ink(img.get_pixel(x, y)))).collect();
+    let (y0, y1) = match (rows.first(), rows.last()) {
+        (Some(&a), Some(&b)) => (a, b + 1),
+        _ => return
+    };
+    let mask: Vec<bool> = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).map(|(x, y)| ink(img.get_pixel(x, y))).collect();
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.put_pixel(x, y, WHITE);
+        }
+    }
+
+    let (cx, cy) = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+    let r = ((x1 - x0) as f32).hypot((y1 - y0) as f32) / 2.0;
+    let (sin, cos) = angle.sin_cos();
+    let range = |c: f32, max: u32| (c - r).max(0.0) as u32..((c + r).ceil() as u32).min(max);
+    for y in range(cy, h) {
+        for x in range(cx, w) {
+            let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+            let sx = (cos * dx + sin * dy + cx).round();
+            let sy = (-sin * dx + cos * dy + cy).round();
+            if sx >= x0 as f32 && sx < x1 as f32 && sy >= y0 as f32 && sy < y1 as f32 {
+                let i = (sy as u32 - y0) * (x1 - x0) + (sx as u32 - x0);
+                if mask[i as usize] {
+                    img.put_pixel(x, y, BLACK);
+                }
+            }
+        }
+    }
+}
+
+impl Speckle {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        for p in img.pixels_mut() {
+            if rng.gen::<f32>() < self.density {
+                *p = BLACK;
+            }
+        }
+    }
+}
+
+impl Grid {
+    pub fn apply(&self, img: &mut RgbImage) {
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            if x % self.gap == 0 || y % self.gap == 0 {
+                *p = BLACK;
+            }
+        }
+    }
+}
+
+impl Wave {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        let (w, h) = img.dimensions();
+        let phase = rng.gen_range(0.0..=2.0 * PI);
+        let src = img.clone();
+        for x in 0..w {
+            let offset = (self.amplitude * (2.0 * PI * x as f32 / self.period + phase).sin()).round() as i64;
+            for y in 0..h {
+                let sy = y as i64 + offset;
+                let p = if sy >= 0 && sy < h as i64 { *src.get_pixel(x, sy as u32) } else { WHITE };
+                img.put_pixel(x, y, p);
+            }
+        }
+    }
+}
+
+impl Dots {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        let (w, h) = (img.width() as i64, img.height() as i64);
+        for _ in 0..self.count {
+            let (cx, cy) = (rng.gen_range(0..w), rng.gen_range(0..h));
+            let r = rng.gen_range(self.min_radius..=self.max_radius) as i64;
+            for y in (cy - r).max(0)..(cy + r + 1).min(h) {
+                for x in (cx - r).max(0)..(cx + r + 1).min(w) {
+                    if (x - cx).pow(2) + (y - cy).pow(2) <= r * r {
+                        img.put_pixel(x as u32, y as u32, BLACK);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Warp {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        const CELL: f32 = 20.0;
+        let (w, h) = img.dimensions();
+        let (px, py) = (rng.gen_range(0.0..=2.0 * PI), rng.gen_range(0.0..=2.0 * PI));
+
+        // Random displacements on a coarse grid which are interpolated between the grid points.
+        let (gw, gh) = ((w as f32 / CELL) as usize + 2, (h as f32 / CELL) as usize + 2);
+        let e = self.elastic;
+        let grid: Vec<(f32, f32)> = (0..gw * gh).map(|_| (rng.gen_range(-e..=e), rng.gen_range(-e..=e))).collect();
+        let displacement = |x: f32, y: f32| {
+            let (gx, gy) = (x / CELL, y / CELL);
+            let (i, j) = (gx as usize, gy as usize);
+            let (fx, fy) = (gx.fract(), gy.fract());
+            let at = |i: usize, j: usize| grid[j * gw + i];
+            let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+            let (a, b, c, d) = (at(i, j), at(i + 1, j), at(i, j + 1), at(i + 1, j + 1));
+            (mix(mix(a.0, b.0, fx), mix(c.0, d.0, fx), fy), mix(mix(a.1, b.1, fx), mix(c.1, d.1, fx), fy))
+        };
+
+        let src = img.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let (ex, ey) = displacement(x as f32, y as f32);
+                let sx = x as f32 + self.amplitude * (2.0 * PI * y as f32 / self.period + px).sin() + ex;
+                let sy = y as f32 + self.amplitude * (2.0 * PI * x as f32 / self.period + py).sin() + ey;
+                let (sx, sy) = (sx.round(), sy.round());
+                let p = if sx >= 0.0 && sy >= 0.0 && (sx as u32) < w && (sy as u32) < h {
+                    *src.get_pixel(sx as u32, sy as u32)
+                } else {
+                    WHITE
+                };
+                img.put_pixel(x, y, p);
+            }
+        }
+    }
+}
+
+impl Occlusion {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        let (w, h) = (img.width() as f32, img.height() as f32);
+        for _ in 0..self.lines {
+            let (x0, y0) = (rng.gen_range(0.0..=w / 4.0), rng.gen_range(0.0..=h));
+            let (x1, y1) = (rng.gen_range(w * 3.0 / 4.0..=w), rng.gen_range(0.0..=h));
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil() as u32;
+            for i in 0..=steps {
+                let t = i as f32 / steps.max(1) as f32;
+                self.dot(img, x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+            }
+        }
+        for _ in 0..self.arcs {
+            let (cx, cy) = (rng.gen_range(0.0..=w), rng.gen_range(0.0..=h));
+            let r = rng.gen_range(h / 4.0..=h);
+            let start = rng.gen_range(0.0..=2.0 * PI);
+            let span = rng.gen_range(PI / 2.0..=PI);
+            let steps = (r * span).ceil() as u32;
+            for i in 0..=steps {
+                let a = start + span * i as f32 / steps.max(1) as f32;
+                self.dot(img, cx + r * a.cos(), cy + r * a.sin());
+            }
+        }
+    }
+
+    fn dot(&self, img: &mut RgbImage, x: f32, y: f32) {
+        let t = self.thickness as i64;
+        for dy in 0..t {
+            for dx in 0..t {
+                let (px, py) = (x as i64 + dx - t / 2, y as i64 + dy - t / 2);
+                if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                    img.put_pixel(px as u32, py as u32, BLACK);
+                }
+            }
+        }
+    }
+}
+
+impl Clutter {
+    /// Draws copies of the characters of `source`, the image before any filter was applied.
+    pub fn apply(&self, img: &mut RgbImage, source: &RgbImage, rng: &mut StdRng) {
+        let glyphs = segments(source);
+        if glyphs.is_empty() {
+            return;
+        }
+        let shade = Rgb([self.shade; 3]);
+        for _ in 0..self.count {
+            let (gx0, gx1) = glyphs[rng.gen_range(0..glyphs.len())];
+            let rows: Vec<u32> = (0..source.height()).filter(|&y| (gx0..gx1).any(|x| ink(source.get_pixel(x, y)))).collect();
+            let (gy0, gy1) = match (rows.first(), rows.last()) {
+                (Some(&a), Some(&b)) => (a, b + 1),
+                _ => continue
+            };
+            // The characters are drawn at half size and only on the background.
+            let (gw, gh) = ((gx1 - gx0) / 2, (gy1 - gy0) / 2);
+            if gw >= img.width() || gh >= img.height() {
+                continue;
+            }
+            let x0 = rng.gen_range(0..img.width() - gw);
+            let y0 = rng.gen_range(0..img.height() - gh);
+            for y in 0..gh {
+                for x in 0..gw {
+                    if ink(source.get_pixel(gx0 + x * 2, gy0 + y * 2)) && !ink(img.get_pixel(x0 + x, y0 + y)) {
+                        img.put_pixel(x0 + x, y0 + y, shade);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Noise {
+    pub fn apply(&self, img: &mut RgbImage, rng: &mut StdRng) {
+        let a = self.amount as i16;
+        for p in img.pixels_mut() {
+            if rng.gen::<f32>() < self.density {
+                for c in p.0.iter_mut() {
+                    *c = (*c as i16 + rng.gen_range(-a..=a)).clamp(0, 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::filters::{parse, segments, Clutter, Dots, Grid, Jitter, Noise, Occlusion, Profile, Speckle, Warp, Wave, BLACK, WHITE};
+    use crate::theme::Theme;
+
+    /// Two black blocks on a white background.
+    fn image() -> RgbImage {
+        let mut img = RgbImage::from_pixel(220, 120, WHITE);
+        for y in 40..80 {
+            for x in (60..80).chain(120..140) {
+                img.put_pixel(x, y, BLACK);
+            }
+        }
+        img
+    }
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(5)
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(segments(&image()), vec![(60, 80), (120, 140)]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let filtered = |f: &dyn Fn(&mut RgbImage, &mut StdRng)| {
+            let (mut a, mut b) = (image(), image());
+            f(&mut a, &mut rng());
+            f(&mut b, &mut rng());
+            // The result depends on the seed only and differs from the original.
+            assert_eq!(a, b);
+            assert_ne!(a, image());
+            a
+        };
+        let img = filtered(&|i, r| Jitter { degrees: 20.0 }.apply(i, r));
+        assert_eq!(img.dimensions(), (220, 120));
+        filtered(&|i, r| Speckle { density: 0.3 }.apply(i, r));
+        filtered(&|i, _| Grid { gap: 6 }.apply(i));
+        filtered(&|i, r| Wave { amplitude: 8.0, period: 200.0 }.apply(i, r));
+        filtered(&|i, r| Dots { count: 10, min_radius: 3, max_radius: 7 }.apply(i, r));
+        filtered(&|i, r| Warp { amplitude: 3.0, period: 40.0, elastic: 2.0 }.apply(i, r));
+        filtered(&|i, r| Occlusion { lines: 2, arcs: 1, thickness: 2 }.apply(i, r));
+        let img = filtered(&|i, r| Clutter { count: 5, shade: 170 }.apply(i, &image(), r));
+        assert!(img.pixels().any(|p| *p == Rgb([170, 170, 170])));
+        filtered(&|i, r| Noise { amount: 30, density: 0.2 }.apply(i, r));
+    }
+
+    #[test]
+    fn test_profile() {
+        let filtered = |p: &Profile| {
+            let mut img = image();
+            p.apply(&mut img, &Theme::default(), &mut rng());
+            img
+        };
+        let p = Profile::builtin("hard");
+        assert_eq!(filtered(&p), filtered(&p));
+        assert_ne!(filtered(&p), image());
+        // Without filters and colors the image is unchanged.
+        assert_eq!(filtered(&Profile::default()), image());
+    }
+
+    #[test]
+    fn test_parse() {
+        let profiles = parse(r#"{"easy": {"noise": {"amount": 10, "density": 0.5}}}"#).unwrap();
+        assert_eq!(profiles, vec![(String::from("easy"), Profile { noise: Some(Noise { amount: 10, density: 0.5 }), ..Profile::default() })]);
+        assert!(parse(r#"{"extreme": {}}"#).is_err());
+        assert!(parse(r#"{"easy": {"warp": {"amplitude": 1, "period": 0}}}"#).is_err());
+        assert!(parse(r#"{"easy": {"noise": {"amount": 10, "density": 2}}}"#).is_err());
+        assert!(parse(r#"{"easy": {"grid": {"gap": 0}}}"#).is_err());
+        assert!(parse(r#"{"easy": {"dots": {"count": 5, "min_radius": 8, "max_radius": 4}}}"#).is_err());
+    }
+}
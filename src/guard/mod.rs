@@ -0,0 +1,152 @@
+//! Request guard which protects routes of other Rocket applications with a solved CAPTCHA.
+//!
+//! The application needs access to the Redis instance of the service, i.e. `REDIS_HOST` must be
+//! set. A route is protected by adding a parameter of type `SolvedCaptcha`:
+//!
+//! ```ignore
+//! #[post("/comment")]
+//! fn comment(captcha: SolvedCaptcha) -> &'static str { "ok" }
+//! ```
+//!
+//! The guard reads either the id of a CAPTCHA and its solution or a verification token, i.e. the
+//! id of a CAPTCHA which has been solved via the service (e.g. by the JavaScript widget). Tokens
+//! can be used only once. Only CAPTCHAs whose solution has not been revealed to the client, i.e.
+//! CAPTCHAs of the widget, are tokens.
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::methods::{Caller, CaptchaError, captcha_redeem, captcha_solution};
+
+/// Names of the headers. The names of the query parameters are the same without the prefix
+/// `X-` in lower case, e.g. `captcha-token`.
+pub const ID_HEADER: &str = "X-Captcha-Id";
+pub const SOLUTION_HEADER: &str = "X-Captcha-Solution";
+pub const TOKEN_HEADER: &str = "X-Captcha-Token";
+
+#[derive(Debug)]
+pub enum GuardError {
+    /// The request contains neither a solution nor a token.
+    Missing,
+    /// The solution is wrong or the token is invalid or has already been used.
+    Rejected,
+    /// The CAPTCHA could not be checked.
+    Failed(CaptchaError),
+}
+
+/// A CAPTCHA which has been solved by the sender of the request.
+#[derive(Debug, Clone)]
+pub struct SolvedCaptcha {
+    id: String,
+}
+
+impl SolvedCaptcha {
+    /// Returns the id of the solved CAPTCHA.
+    pub fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    /// Checks the solution of a CAPTCHA or, if no solution is given, redeems the token `id`.
+    ///
+    /// Request guards cannot read the body of a request. Routes which receive the CAPTCHA in a
+    /// form field call this function with the values of the form.
+    pub async fn verify(id: &str, solution: Option<&str>) -> Result<SolvedCaptcha, GuardError> {
+        let accepted = match solution {
+            Some(s) => match captcha_solution(id.to_string(), s.to_string(), None).await {
+                Ok(d) => d.accepted(),
+                Err(CaptchaError::NotFound) | Err(CaptchaError::InvalidParameters) => false,
+                Err(e) => return Err(GuardError::Failed(e))
+            },
+            None => match captcha_redeem(id.to_string(), Caller::Trusted).await {
+                Ok(r) => r.redeemed(),
+                Err(CaptchaError::InvalidParameters) => false,
+                Err(e) => return Err(GuardError::Failed(e))
+            }
+        };
+        if accepted {
+            info!("CAPTCHA [{}] verified.", id);
+            Ok(SolvedCaptcha { id: id.to_string() })
+        } else {
+            info!("CAPTCHA [{}] rejected.", id);
+            Err(GuardError::Rejected)
+        }
+    }
+}
+
+/// Returns the value of a header or, if the header does not exist, of a query parameter.
+fn value<'r>(request: &'r Request<'_>, header: &str) -> Option<&'r str> {
+    request.headers().get_one(header)
+        .or_else(|| request.query_value::<&str>(&header[2..].to_lowercase()).and_then(|r| r.ok()))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SolvedCaptcha {
+    type Error = GuardError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let r = match (value(request, ID_HEADER), value(request, SOLUTION_HEADER), value(request, TOKEN_HEADER)) {
+            (Some(id), Some(solution), _) => SolvedCaptcha::verify(id, Some(solution)).await,
+            (_, _, Some(token)) => SolvedCaptcha::verify(token, None).await,
+            _ => Err(GuardError::Missing)
+        };
+        match r {
+            Ok(c) => Outcome::Success(c),
+            Err(GuardError::Failed(e)) => {
+                error!("Could not verify CAPTCHA [{:?}].", e);
+                Outcome::Error((Status::InternalServerError, GuardError::Failed(e)))
+            },
+            Err(e) => Outcome::Error((Status::Forbidden, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rocket::http::{Header, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::{get, routes};
+
+    use crate::guard::SolvedCaptcha;
+    use crate::methods::{captcha_new, captcha_new_token, captcha_solution};
+
+    #[get("/protected")]
+    fn protected(captcha: SolvedCaptcha) -> String {
+        captcha.uuid()
+    }
+
+    #[tokio::test]
+    async fn test_guard() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let rocket = rocket::build().mount("/", routes![protected]);
+        let client = Client::tracked(rocket).await.expect("valid rocket");
+        assert_eq!(client.get("/protected").dispatch().await.status(), Status::Forbidden);
+
+        // Id and solution in headers.
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        let (id, solution) = (c.uuid(), c.solution().unwrap().to_string());
+        let r = client.get("/protected")
+            .header(Header::new("X-Captcha-Id", id.clone()))
+            .header(Header::new("X-Captcha-Solution", "wrong"))
+            .dispatch().await;
+        assert_eq!(r.status(), Status::Forbidden);
+        let r = client.get("/protected")
+            .header(Header::new("X-Captcha-Id", id.clone()))
+            .header(Header::new("X-Captcha-Solution", solution))
+            .dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.into_string().await.unwrap(), id);
+
+        // A token in the query which can be used only once.
+        let c = captcha_new_token("easy".into(), "2".into(), "60".into(), "test".into()).await.unwrap();
+        let uri = format!("/protected?captcha-token={}", c.uuid());
+        assert_eq!(client.get(uri.clone()).dispatch().await.status(), Status::Forbidden);
+        assert!(captcha_solution(c.uuid(), c.solution().unwrap().to_string(), None).await.unwrap().accepted());
+        assert_eq!(client.get(uri.clone()).dispatch().await.status(), Status::Ok);
+        assert_eq!(client.get(uri).dispatch().await.status(), Status::Forbidden);
+
+        env::remove_var("REDIS_HOST");
+    }
+}
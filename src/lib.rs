@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate log;
+extern crate rocket;
 extern crate captcha;
 extern crate uuid;
 extern crate base64;
@@ -9,8 +10,40 @@ extern crate serde_derive;
 extern crate serde;
 extern crate redis;
 extern crate time;
+extern crate sha2;
+extern crate crc32fast;
+extern crate rand;
+extern crate image;
+extern crate ab_glyph;
+extern crate hound;
+extern crate tokio;
+extern crate rust_captcha_types;
+#[cfg(feature = "tower")]
+extern crate http;
+#[cfg(feature = "tower")]
+extern crate tower_layer;
+#[cfg(feature = "tower")]
+extern crate tower_service;
 
 pub mod methods;
 pub mod requesthandler;
 pub mod validation;
 pub mod persistence;
+pub mod ratelimit;
+pub mod pow;
+pub mod adaptive;
+pub mod admin;
+pub mod widget;
+pub mod cors;
+pub mod logging;
+pub mod shutdown;
+pub mod tls;
+pub mod guard;
+pub mod sites;
+pub mod theme;
+pub mod filters;
+pub mod animation;
+pub mod audio;
+pub mod ocr;
+#[cfg(feature = "tower")]
+pub mod middleware;
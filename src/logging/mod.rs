@@ -0,0 +1,326 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use log::Record;
+use serde_json::{self, Map, Value};
+use uuid::Uuid;
+
+/// Context of the request which is processed by the current task. The fields which are set
+/// are added to each log line in the JSON format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Context {
+    pub request_id: Option<String>,
+    pub route: Option<String>,
+    pub client_id: Option<String>,
+    pub ip: Option<String>,
+    pub captcha_id: Option<String>,
+    pub outcome: Option<String>,
+    pub status: Option<u16>,
+    pub latency_ms: Option<u64>,
+}
+
+/// A context which is shared by the tasks that process a request.
+pub type SharedContext = Arc<Mutex<Context>>;
+
+tokio::task_local! {
+    static CONTEXT: SharedContext;
+}
+
+/// Creates the context of a new request.
+pub fn new_context(request_id: &str, client_id: Option<&str>, ip: Option<IpAddr>) -> SharedContext {
+    Arc::new(Mutex::new(Context {
+        request_id: Some(request_id.to_string()),
+        client_id: client_id.map(|s| s.to_string()),
+        ip: ip.map(|i| i.to_string()),
+        ..Context::default()
+    }))
+}
+
+/// Runs the future `f` within the request context `ctx`.
+pub async fn scope<F: Future>(ctx: SharedContext, f: F) -> F::Output {
+    CONTEXT.scope(ctx, f).await
+}
+
+/// Runs `f` within the request context `ctx`.
+pub fn sync_scope<R, F: FnOnce() -> R>(ctx: SharedContext, f: F) -> R {
+    CONTEXT.sync_scope(ctx, f)
+}
+
+/// Updates the request context of the current task. Does nothing outside of a request context.
+pub fn update<F: FnOnce(&mut Context)>(f: F) {
+    let _ = CONTEXT.try_with(|c| f(&mut c.lock().unwrap_or_else(|e| e.into_inner())));
+}
+
+/// Sets the id of the CAPTCHA the current request refers to.
+pub fn set_captcha(id: &str) {
+    update(|c| c.captcha_id = Some(id.to_string()));
+}
+
+/// Sets the outcome of the current request, e.g. `created` or `accepted`.
+pub fn set_outcome(outcome: &str) {
+    update(|c| c.outcome = Some(outcome.to_string()));
+}
+
+/// Returns the id of a request. The id sent by the client in the header `X-Request-ID` is used if
+/// it is valid, otherwise a new id is created.
+pub fn request_id(header: Option<&str>) -> String {
+    match header {
+        Some(h) if !h.is_empty() && h.len() <= 64 && h.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => h.to_string(),
+        _ => Uuid::new_v4().to_hyphenated().to_string()
+    }
+}
+
+/// Removes solutions from a log message. Solutions are part of the path `/solution/<id>/<solution>`
+/// which is logged by the HTTP server.
+pub fn redact(msg: &str) -> String {
+    const PATH: &str = "/solution/";
+    let mut r = String::with_capacity(msg.len());
+    let mut rest = msg;
+    while let Some(i) = rest.find(PATH) {
+        let (head, tail) = rest.split_at(i + PATH.len());
+        r.push_str(head);
+        let id_end = tail.find(|c: char| c == '/' || c.is_whitespace()).unwrap_or(tail.len());
+        r.push_str(&tail[..id_end]);
+        rest = &tail[id_end..];
+        if rest.starts_with('/') {
+            r.push_str("/***");
+            rest = &rest[rest.find(|c: char| c.is_whitespace() || c == ':' || c == '?').unwrap_or(rest.len())..];
+        }
+    }
+    r.push_str(rest);
+    r
+}
+
+/// Returns true if the environment variable `LOG_FORMAT` is set to `json`.
+pub fn json_enabled() -> bool {
+    env::var("LOG_FORMAT").map(|s| s.trim() == "json").unwrap_or(false)
+}
+
+fn timestamp() -> String {
+    time::now_utc().rfc3339().to_string()
+}
+
+fn context_fields(m: &mut Map<String, Value>) {
+    let _ = CONTEXT.try_with(|c| {
+        let c = c.lock().unwrap_or_else(|e| e.into_inner());
+        let fields = vec![
+            ("request_id", c.request_id.clone().map(Value::from)),
+            ("route", c.route.clone().map(Value::from)),
+            ("client_id", c.client_id.clone().map(Value::from)),
+            ("ip", c.ip.clone().map(Value::from)),
+            ("captcha_id", c.captcha_id.clone().map(Value::from)),
+            ("outcome", c.outcome.clone().map(Value::from)),
+            ("status", c.status.map(Value::from)),
+            ("latency_ms", c.latency_ms.map(Value::from)),
+        ];
+        for (k, v) in fields {
+            if let Some(v) = v {
+                m.insert(k.to_string(), v);
+            }
+        }
+    });
+}
+
+/// Formats a log record as a line of JSON with the fields of the current request context.
+pub fn format<W: Write>(w: &mut W, record: &Record) -> io::Result<()> {
+    let mut m = Map::new();
+    m.insert(String::from("timestamp"), Value::from(timestamp()));
+    m.insert(String::from("level"), Value::from(record.level().to_string()));
+    m.insert(String::from("target"), Value::from(record.target()));
+    m.insert(String::from("message"), Value::from(redact(&record.args().to_string())));
+    context_fields(&mut m);
+    writeln!(w, "{}", Value::Object(m))
+}
+
+/// Formats a log record as a line of text.
+pub fn format_text<W: Write>(w: &mut W, record: &Record) -> io::Result<()> {
+    writeln!(w, "[{} {:<5} {}] {}", timestamp(), record.level(), record.target(), redact(&record.args().to_string()))
+}
+
+/// Security relevant events which are written to the audit stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditEvent {
+    /// A CAPTCHA has been created.
+    Created,
+    /// A solution has been checked.
+    Attempt,
+    /// The last try of a CAPTCHA has been used without success.
+    Exhausted,
+    /// A solution has been sent for an expired CAPTCHA.
+    Expired,
+}
+
+impl AuditEvent {
+    fn name(&self) -> &'static str {
+        match *self {
+            AuditEvent::Created   => "created",
+            AuditEvent::Attempt   => "attempt",
+            AuditEvent::Exhausted => "exhausted",
+            AuditEvent::Expired   => "expired",
+        }
+    }
+}
+
+/// Destination of the audit stream. Configured via the environment variable `AUDIT_LOG` which
+/// is either `stdout`, `stderr` or the path of a file. If it is not set, no audit events are
+/// written.
+fn audit_destination() -> Option<String> {
+    env::var("AUDIT_LOG").ok().filter(|s| !s.trim().is_empty())
+}
+
+fn audit_line(event: AuditEvent, captcha_id: &str, outcome: Option<&str>) -> String {
+    let mut m = Map::new();
+    m.insert(String::from("timestamp"), Value::from(timestamp()));
+    m.insert(String::from("event"), Value::from(event.name()));
+    context_fields(&mut m);
+    m.insert(String::from("captcha_id"), Value::from(captcha_id));
+    match outcome {
+        Some(o) => { m.insert(String::from("outcome"), Value::from(o)); },
+        None    => { m.remove("outcome"); }
+    }
+    // Fields of the access log do not belong to the event.
+    m.remove("status");
+    m.remove("latency_ms");
+    serde_json::to_string(&Value::Object(m)).unwrap_or_default() + "\n"
+}
+
+/// The audit file and its path. It is opened with the first event and kept open, so that events
+/// are only written to the buffer while a request is processed.
+static AUDIT_FILE: Mutex<Option<(String, BufWriter<File>)>> = Mutex::new(None);
+
+fn write_audit_file(path: &str, line: &str) -> io::Result<()> {
+    let mut file = AUDIT_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if file.as_ref().is_none_or(|(p, _)| p != path) {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        *file = Some((path.to_string(), BufWriter::new(f)));
+    }
+    match file.as_mut() {
+        Some((_, w)) => w.write_all(line.as_bytes()),
+        None         => Ok(())
+    }
+}
+
+/// Writes the buffered events to the audit file.
+pub fn flush_audit() {
+    let mut file = AUDIT_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((path, w)) = file.as_mut() {
+        if let Err(e) = w.flush() {
+            error!("Could not flush audit events to [{}] [{}].", path, e);
+        }
+    }
+}
+
+/// Writes an event to the audit stream. Solutions are never part of an event.
+pub fn audit(event: AuditEvent, captcha_id: &str, outcome: Option<&str>) {
+    let dest = match audit_destination() {
+        Some(d) => d,
+        None    => return
+    };
+    let line = audit_line(event, captcha_id, outcome);
+    let r = match dest.as_str() {
+        "stdout" => io::stdout().lock().write_all(line.as_bytes()),
+        "stderr" => io::stderr().lock().write_all(line.as_bytes()),
+        path => write_audit_file(path, &line)
+    };
+    if let Err(e) = r {
+        error!("Could not write audit event to [{}] [{}].", dest, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use log::{Level, Record};
+    use serde_json::{self, Value};
+    use crate::logging::{new_context, sync_scope, set_captcha, set_outcome, format, audit, audit_line, flush_audit, redact, request_id, AuditEvent};
+
+    #[test]
+    fn test_format() {
+        let ctx = new_context("req-1", Some("client-a"), None);
+        let mut buf = vec![];
+        sync_scope(ctx.clone(), || {
+            set_captcha("id-1");
+            format(&mut buf, &Record::builder()
+                .args(format_args!("Created new CAPTCHA."))
+                .level(Level::Info)
+                .target("rust_captcha")
+                .build()).unwrap();
+        });
+        let v: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(v["level"], "INFO");
+        assert_eq!(v["message"], "Created new CAPTCHA.");
+        assert_eq!(v["request_id"], "req-1");
+        assert_eq!(v["client_id"], "client-a");
+        assert_eq!(v["captcha_id"], "id-1");
+        assert!(v.get("route").is_none());
+
+        assert_eq!(ctx.lock().unwrap().captcha_id, Some(String::from("id-1")));
+
+        // Outside of a request context no fields are added.
+        let mut buf = vec![];
+        format(&mut buf, &Record::builder().args(format_args!("x")).level(Level::Info).build()).unwrap();
+        let v: Value = serde_json::from_slice(&buf).unwrap();
+        assert!(v.get("request_id").is_none());
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(request_id(Some("abc-123")), "abc-123");
+        assert_eq!(request_id(None).len(), 36);
+        assert_eq!(request_id(Some("")).len(), 36);
+        assert_eq!(request_id(Some("a\"b")).len(), 36);
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("POST /solution/abc/xyz:"), "POST /solution/abc/***:");
+        assert_eq!(redact("POST /solution/abc/xyz application/json"), "POST /solution/abc/*** application/json");
+        assert_eq!(redact("/solution/a/b and /solution/c/d"), "/solution/a/*** and /solution/c/***");
+        assert_eq!(redact("POST /solution/abc"), "POST /solution/abc");
+        assert_eq!(redact("Created new CAPTCHA."), "Created new CAPTCHA.");
+    }
+
+    #[test]
+    fn test_audit_line() {
+        let ctx = new_context("req-2", None, Some("10.0.0.1".parse().unwrap()));
+        let line = sync_scope(ctx, || {
+            set_outcome("rejected");
+            audit_line(AuditEvent::Attempt, "id-2", Some("rejected"))
+        });
+        let v: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["event"], "attempt");
+        assert_eq!(v["captcha_id"], "id-2");
+        assert_eq!(v["outcome"], "rejected");
+        assert_eq!(v["request_id"], "req-2");
+        assert_eq!(v["ip"], "10.0.0.1");
+
+        let v: Value = serde_json::from_str(&audit_line(AuditEvent::Expired, "id-2", None)).unwrap();
+        assert!(v.get("outcome").is_none());
+    }
+
+    #[test]
+    fn test_audit_file() {
+        let path = env::temp_dir().join(format!("audit-{}.log", std::process::id()));
+        fs::remove_file(&path).ok();
+        env::set_var("AUDIT_LOG", &path);
+
+        audit(AuditEvent::Created, "id-3", None);
+        audit(AuditEvent::Attempt, "id-3", Some("accepted"));
+        flush_audit();
+        env::remove_var("AUDIT_LOG");
+
+        let events: Vec<Value> = fs::read_to_string(&path).unwrap().lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .filter(|v: &Value| v["captcha_id"] == "id-3")
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "created");
+        assert_eq!(events[1]["outcome"], "accepted");
+        fs::remove_file(&path).ok();
+    }
+}
@@ -1,19 +1,43 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-
-#[macro_use] extern crate log;
 #[macro_use] extern crate rocket;
+#[macro_use] extern crate log;
 extern crate env_logger;
 extern crate rust_captcha;
 extern crate serde_json;
+extern crate serde;
+extern crate rust_captcha_types;
 
 use std::env;
+use std::future::Future;
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use rust_captcha::requesthandler::{req_captcha_new, req_captcha_newget, req_captcha_solution};
-use rust_captcha::methods::CaptchaError;
-use rocket::response::content;
-use serde_json::{json, Value};
+use rust_captcha::requesthandler::{req_captcha_new, req_captcha_newget, req_captcha_solution, req_captcha_status, req_captcha_refresh, req_captcha_batch, req_captcha_redeem, UNKNOWN_CLIENT};
+use rust_captcha::requesthandler::{req_admin_inspect, req_admin_revoke, req_admin_list, req_admin_purge};
+use rust_captcha::requesthandler::{req_widget_new, req_widget_refresh, req_widget_site, req_widget_audio};
+use rust_captcha::admin;
+use rust_captcha::methods::{CaptchaError, CaptchaSolutionResponse, Verdict, ZipStream};
+use rust_captcha_types::Envelope;
+use rust_captcha::ratelimit::{self, Limited};
+use rust_captcha::widget;
+use rust_captcha::cors;
+use rust_captcha::logging::{self, SharedContext};
+use rust_captcha::shutdown;
+use rust_captcha::sites;
+use rust_captcha::filters;
+use rust_captcha::tls;
+use rocket::futures::stream;
+use rocket::response::{self, content, Responder};
+use rocket::response::stream::ByteStream;
+use serde::Serialize;
 use rocket::request::FromRequest;
-use rocket::{Request, request};
+use rocket::http::{ContentType, Method, Status};
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::mtls::Certificate;
+use rocket::figment::Figment;
+use rocket::{Data, Request, Response, Shutdown, request};
 
 const PORT: u16 = 8000;
 
@@ -27,6 +51,34 @@ fn precondition_checks() -> bool {
     }
 }
 
+/// Returns the configuration of the server. TLS is enabled if `tls` is given. Client
+/// certificates are requested but not mandatory, so that only the routes which require them
+/// reject connections without them.
+fn config(tls: Option<&tls::Settings>) -> Figment {
+    let figment = rocket::Config::figment()
+        .merge(("shutdown.grace", shutdown::timeout()));
+    match tls {
+        None => figment,
+        Some(s) => {
+            info!("TLS enabled with certificate [{}].", s.cert);
+            let figment = figment.merge(("tls.certs", &s.cert)).merge(("tls.key", &s.key));
+            match s.client_ca {
+                Some(ref ca) => {
+                    info!("Client certificates of CA [{}] enabled.", ca);
+                    figment.merge(("tls.mutual.ca_certs", ca)).merge(("tls.mutual.mandatory", false))
+                },
+                None => figment
+            }
+        }
+    }
+}
+
+/// Checks whether the request has a valid client certificate if the routes of `group` require
+/// one.
+async fn client_cert(request: &Request<'_>, group: &str) -> bool {
+    !tls::client_cert_required(group) || request.guard::<Certificate<'_>>().await.succeeded().is_some()
+}
+
 struct ClientId(String);
 
 #[derive(Debug)]
@@ -38,99 +90,548 @@ fn client_id(cid: ClientId) -> String { // TODO validate
     }
 }
 
-impl<'a, 'r> FromRequest<'a, 'r> for ClientId {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientId {
     type Error = ClientIdError;
 
-    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         let client_ids: Vec<_> = request.headers().get("x-client-id").collect();
         match client_ids.len() {
-            0 => request::Outcome::Success(ClientId(String::from("<unknown>"))),
+            0 => request::Outcome::Success(ClientId(String::from(UNKNOWN_CLIENT))),
             _ => request::Outcome::Success(ClientId(client_ids[0].to_string()))
         }
     }
 }
 
+/// The secret of a site from the header `X-Site-Secret`.
+struct SiteSecret(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SiteSecret {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(SiteSecret(request.headers().get_one("x-site-secret").map(String::from)))
+    }
+}
+
+struct ClientIp(Option<IpAddr>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let forwarded_for = request.headers().get("x-forwarded-for").collect::<Vec<_>>().join(",");
+        request::Outcome::Success(ClientIp(ratelimit::client_ip(
+            request.remote().map(|addr| addr.ip()),
+            Some(forwarded_for.as_str()).filter(|s| !s.is_empty()),
+            &ratelimit::trusted_proxies()
+        )))
+    }
+}
+
+struct RateLimit;
+
+#[derive(Debug)]
+struct RateLimitError;
+
+struct RetryAfter(u64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimit {
+    type Error = RateLimitError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let route = request.route().and_then(|r| r.name.as_deref()).unwrap_or("unknown");
+        let clientid = request.headers().get_one("x-client-id");
+        let ip = request.guard::<ClientIp>().await.succeeded().and_then(|ClientIp(ip)| ip);
+
+        // A batch takes one token per CAPTCHA.
+        let tokens = match route {
+            "batch" => request.routed_segment(2).and_then(|c| c.parse::<u32>().ok()).unwrap_or(1),
+            _ => 1
+        };
+
+        match ratelimit::check(route, clientid, ip, tokens).await {
+            Ok(_) => request::Outcome::Success(RateLimit),
+            Err(limited) => {
+                info!("Rate limit exceeded for route [{}], clientid [{}], ip [{:?}].", route, clientid.unwrap_or(UNKNOWN_CLIENT), ip);
+                // A request which is larger than the bucket will never be allowed, so there is no
+                // point in retrying it.
+                let wait = match limited {
+                    Limited::Wait(w) => w,
+                    Limited::Exceeded => 0
+                };
+                request.local_cache(|| RetryAfter(wait));
+                request::Outcome::Error((Status::TooManyRequests, RateLimitError))
+            }
+        }
+    }
+}
+
+struct TooManyRequests(u64);
+
+impl<'r> Responder<'r, 'static> for TooManyRequests {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build_from(content::RawJson(error(CResult::TooManyRequests)).respond_to(req)?);
+        response.status(Status::TooManyRequests);
+        if self.0 > 0 {
+            response.raw_header("Retry-After", self.0.to_string());
+        }
+        response.ok()
+    }
+}
+
+#[catch(429)]
+fn too_many_requests(req: &Request) -> TooManyRequests {
+    TooManyRequests(req.local_cache(|| RetryAfter(1)).0)
+}
+
+struct Admin;
+
+#[derive(Debug)]
+struct AdminError;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = AdminError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = request.headers().get_one("authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+        if !client_cert(request, tls::ADMIN).await {
+            warn!("Admin request without client certificate [{}].", request.uri());
+            return request::Outcome::Error((Status::Unauthorized, AdminError));
+        }
+        match token {
+            Some(t) if admin::authorized(t) => request::Outcome::Success(Admin),
+            _ => {
+                warn!("Unauthorized admin request [{}].", request.uri());
+                request::Outcome::Error((Status::Unauthorized, AdminError))
+            }
+        }
+    }
+}
+
+/// Backends which verify CAPTCHAs, i.e. query their status or redeem their tokens.
+struct Verifier;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Verifier {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if client_cert(request, tls::VERIFY).await {
+            request::Outcome::Success(Verifier)
+        } else {
+            warn!("Request without client certificate [{}].", request.uri());
+            request::Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+#[catch(401)]
+fn unauthorized() -> content::RawJson<String> {
+    content::RawJson(error(CResult::Unauthorized))
+}
+
+/// Adds the CORS headers for allowed origins and security headers to all responses.
+struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info { name: "CORS and security headers", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let preflight = request.method() == Method::Options;
+        if let Some(origin) = request.headers().get_one("origin") {
+            let allowed = if preflight {
+                cors::all_origins()
+            } else {
+                cors::origins_for(request.headers().get_one("x-client-id"))
+            };
+            match cors::allow_origin(origin, &allowed) {
+                Some(o) => {
+                    response.set_raw_header("Access-Control-Allow-Origin", o);
+                    if preflight {
+                        response.set_raw_header("Access-Control-Allow-Methods", cors::ALLOWED_METHODS);
+                        response.set_raw_header("Access-Control-Allow-Headers", cors::ALLOWED_HEADERS);
+                        response.set_raw_header("Access-Control-Max-Age", cors::max_age().to_string());
+                    }
+                },
+                None => info!("Origin [{}] not allowed for [{}].", origin, request.uri())
+            }
+            response.adjoin_raw_header("Vary", "Origin");
+        }
+        let content_type = response.content_type().map(|c| c.to_string());
+        for (name, value) in cors::security_headers(content_type.as_deref()) {
+            response.set_raw_header(name, value);
+        }
+    }
+}
+
+struct RequestId(String);
+
+struct RequestStart(Instant);
+
+/// The logging context of a request. Log lines which are written within the context contain the
+/// request id, the client id and the id of the CAPTCHA.
+#[derive(Clone)]
+struct RequestContext(SharedContext);
+
+impl RequestContext {
+    async fn scope<F: Future>(self, f: F) -> F::Output {
+        logging::scope(self.0, f).await
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestContext {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(request.local_cache(|| RequestContext(logging::new_context("", None, None))).clone())
+    }
+}
+
+/// Assigns an id to each request and logs the route, the outcome and the latency when the
+/// response is sent.
+struct RequestLog;
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info { name: "Request log", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = logging::request_id(request.headers().get_one("x-request-id"));
+        let ip = request.guard::<ClientIp>().await.succeeded().and_then(|ClientIp(ip)| ip);
+        let ctx = logging::new_context(&id, request.headers().get_one("x-client-id"), ip);
+        request.local_cache(|| RequestContext(ctx));
+        request.local_cache(|| RequestStart(Instant::now()));
+        request.local_cache(|| RequestId(id));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let elapsed = request.local_cache(|| RequestStart(Instant::now())).0.elapsed();
+        let route = request.route().and_then(|r| r.name.as_deref()).unwrap_or("unknown");
+        let ctx = request.local_cache(|| RequestContext(logging::new_context("", None, None))).0.clone();
+        logging::sync_scope(ctx, || {
+            logging::update(|c| {
+                c.route = Some(route.to_string());
+                c.status = Some(response.status().code);
+                c.latency_ms = Some(elapsed.as_millis() as u64);
+            });
+            info!("Request [{}] finished with status [{}] in [{:?}].", route, response.status().code, elapsed);
+        });
+        response.set_raw_header("X-Request-ID", request.local_cache(|| RequestId(String::new())).0.clone());
+    }
+}
+
 #[derive(Clone)]
 enum CResult {
     Processed = 0,
     InternalError = 1,
-    InvalidParameters = 2
+    InvalidParameters = 2,
+    TooManyRequests = 3,
+    Unauthorized = 4,
+    NotFound = 5
 }
 
-fn error(code: CResult) -> Value {
-    let result_str = vec!["processed", "internal error", "invalid parameters"];
-    json!({
-        "error_code": code.clone() as u32,
-        "error_msg": result_str[code as usize],
-        "result": ""
-    })
+const RESULT_STR: [&str; 6] = ["processed", "internal error", "invalid parameters", "too many requests", "unauthorized", "not found"];
+
+/// Wraps the result of a request into the envelope which is sent to the client.
+fn envelope<T: Serialize>(code: CResult, result: T) -> String {
+    let e = Envelope {
+        error_code: code.clone() as u32,
+        error_msg: RESULT_STR[code as usize].to_string(),
+        result
+    };
+    serde_json::to_string(&e).unwrap_or_else(|_| envelope(CResult::InternalError, ""))
+}
+
+fn error(code: CResult) -> String {
+    envelope(code, "")
 }
 
-fn not_found(code: CResult) -> Value {
-    let result_str = vec!["processed", "internal error", "invalid parameters"];
-    json!({
-        "error_code": code.clone() as u32,
-        "error_msg": result_str[code as usize],
-        "result": json!({
-            "solution": "not found",
-            "trials_left": 0
-        })
+fn create_response<T: Serialize>(r: Result<T, CaptchaError>) -> content::RawJson<String> {
+    content::RawJson(match r {
+        Ok(result) => envelope(CResult::Processed, result),
+        Err(e) => match e {
+            CaptchaError::InvalidParameters => error(CResult::InvalidParameters),
+            CaptchaError::CaptchaGeneration => error(CResult::InternalError),
+            CaptchaError::Uuid => error(CResult::InternalError),
+            CaptchaError::ToJson => error(CResult::InternalError),
+            CaptchaError::Persist => error(CResult::InternalError),
+            CaptchaError::NotFound => error(CResult::NotFound),
+            CaptchaError::TooManyRefreshes => error(CResult::TooManyRequests),
+            CaptchaError::Unauthorized => error(CResult::Unauthorized),
+            CaptchaError::Unexpected => error(CResult::InternalError)
+        }
     })
 }
 
-fn create_response(r: Result<String, CaptchaError>) -> content::Json<String> {
-    let result_str = vec!["processed", "internal error", "invalid parameters"];
-    let ret = match r {
-        Err(e) => {
-            match e {
-                CaptchaError::InvalidParameters => error(CResult::InvalidParameters),
-                CaptchaError::CaptchaGeneration => error(CResult::InternalError),
-                CaptchaError::Uuid => error(CResult::InternalError),
-                CaptchaError::ToJson => error(CResult::InternalError),
-                CaptchaError::Persist => error(CResult::InternalError),
-                CaptchaError::NotFound => not_found(CResult::Processed),
-                CaptchaError::Unexpected => error(CResult::InternalError)
-            }
+// Rocket passes each segment of the path as an argument.
+#[allow(clippy::too_many_arguments)]
+#[post("/new/<difficulty>/<max_tries>/<ttl>?<seed>")]
+async fn new(ctx: RequestContext, _limit: RateLimit, difficulty: String, max_tries: String, ttl: String, seed: Option<u64>, clientid: ClientId, ip: ClientIp) -> content::RawJson<String> {
+    create_response(ctx.scope(req_captcha_new(difficulty, max_tries, ttl, seed, client_id(clientid), ip.0)).await.map(|d| d.response(true)))
+}
+
+#[get("/new/<difficulty>?<seed>")]
+async fn new_diff_only(ctx: RequestContext, _limit: RateLimit, difficulty: String, seed: Option<u64>, clientid: ClientId, ip: ClientIp) -> content::RawJson<String> {
+    create_response(ctx.scope(req_captcha_newget(difficulty, seed, client_id(clientid), ip.0)).await.map(|d| d.response(true)))
+}
+
+enum Batch {
+    Json(content::RawJson<String>),
+    Zip(ZipStream),
+}
+
+impl<'r> Responder<'r, 'r> for Batch {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            Batch::Json(json) => json.respond_to(req),
+            Batch::Zip(zip) => Response::build_from(ByteStream(stream::iter(zip)).respond_to(req)?)
+                .header(ContentType::new("application", "zip"))
+                .raw_header("Content-Disposition", "attachment; filename=\"captchas.zip\"")
+                .ok()
+        }
+    }
+}
+
+// Rocket passes each segment of the path as an argument.
+#[allow(clippy::too_many_arguments)]
+#[post("/batch/<difficulty>/<count>/<max_tries>/<ttl>?<format>")]
+async fn batch(ctx: RequestContext, _limit: RateLimit, difficulty: String, count: String, max_tries: String, ttl: String, format: Option<String>, clientid: ClientId, ip: ClientIp) -> Batch {
+    let r = ctx.scope(req_captcha_batch(difficulty, count, max_tries, ttl, client_id(clientid), ip.0)).await;
+    match format.as_deref() {
+        Some("zip") => match r.and_then(|b| b.into_zip()) {
+            Ok(zip) => Batch::Zip(zip),
+            Err(e) => Batch::Json(create_response::<()>(Err(e)))
         },
-        Ok(json) => {
-            let data: Value = serde_json::from_str(&json).unwrap();
-            json!({
-                "error_code": CResult::Processed as u32,
-                "error_msg": result_str[CResult::Processed as usize],
-                "result": data
-            })
+        _ => Batch::Json(create_response(r.map(|b| b.response())))
+    }
+}
+
+#[post("/solution/<id>/<solution>")]
+async fn solution(ctx: RequestContext, _limit: RateLimit, id: String, solution: String, clientid: ClientId, ip: ClientIp) -> content::RawJson<String> {
+    // Unknown CAPTCHAs are a verdict of the check and not an error.
+    create_response(match ctx.scope(req_captcha_solution(id, solution, client_id(clientid), ip.0)).await {
+        Err(CaptchaError::NotFound) => Ok(CaptchaSolutionResponse::new(Verdict::NotFound)),
+        r => r.map(|d| d.response())
+    })
+}
+
+#[get("/captcha/<id>/status")]
+async fn status(ctx: RequestContext, verifier: Option<Verifier>, _limit: RateLimit, id: String, secret: SiteSecret, clientid: ClientId) -> content::RawJson<String> {
+    create_response(ctx.scope(req_captcha_status(id, secret.0, verifier.is_some(), client_id(clientid))).await)
+}
+
+#[post("/captcha/<id>/redeem")]
+async fn redeem(ctx: RequestContext, _verifier: Verifier, _limit: RateLimit, id: String, secret: SiteSecret, clientid: ClientId) -> content::RawJson<String> {
+    create_response(ctx.scope(req_captcha_redeem(id, secret.0, client_id(clientid))).await)
+}
+
+#[post("/captcha/<id>/refresh")]
+async fn refresh(ctx: RequestContext, _limit: RateLimit, id: String, clientid: ClientId) -> content::RawJson<String> {
+    create_response(ctx.scope(req_captcha_refresh(id, client_id(clientid))).await)
+}
+
+#[get("/widget.js")]
+fn widget_js() -> content::RawJavaScript<String> {
+    content::RawJavaScript(widget::script())
+}
+
+#[get("/widget.css")]
+fn widget_css() -> content::RawCss<String> {
+    content::RawCss(widget::style())
+}
+
+#[post("/widget/new/<difficulty>/<max_tries>/<ttl>")]
+async fn widget_new(ctx: RequestContext, _limit: RateLimit, difficulty: String, max_tries: String, ttl: String, clientid: ClientId, ip: ClientIp) -> content::RawJson<String> {
+    create_response(ctx.scope(req_widget_new(difficulty, max_tries, ttl, client_id(clientid), ip.0)).await)
+}
+
+#[get("/widget/site")]
+async fn widget_site(ctx: RequestContext, clientid: ClientId) -> content::RawJson<String> {
+    create_response(ctx.scope(req_widget_site(client_id(clientid))).await)
+}
+
+#[post("/widget/captcha/<id>/refresh")]
+async fn widget_refresh(ctx: RequestContext, _limit: RateLimit, id: String, clientid: ClientId) -> content::RawJson<String> {
+    create_response(ctx.scope(req_widget_refresh(id, client_id(clientid))).await)
+}
+
+enum Audio {
+    Json(content::RawJson<String>),
+    Wav(Vec<u8>),
+}
+
+impl<'r> Responder<'r, 'static> for Audio {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            Audio::Json(json) => json.respond_to(req),
+            Audio::Wav(data) => Response::build()
+                .header(ContentType::WAV)
+                .raw_header("Cache-Control", "no-store")
+                .sized_body(data.len(), Cursor::new(data))
+                .ok()
         }
-    };
+    }
+}
 
-    content::Json(ret.to_string())
+#[get("/widget/captcha/<id>/audio")]
+async fn widget_audio(ctx: RequestContext, _limit: RateLimit, id: String, clientid: ClientId) -> Audio {
+    match ctx.scope(req_widget_audio(id, client_id(clientid))).await {
+        Ok(data) => Audio::Wav(data),
+        Err(e) => Audio::Json(create_response::<()>(Err(e)))
+    }
 }
 
-#[post("/new/<difficulty>/<max_tries>/<ttl>")]
-fn new(difficulty: String, max_tries: String, ttl: String, clientid: ClientId) -> content::Json<String> {
-    create_response(req_captcha_new(difficulty, max_tries, ttl, client_id(clientid)))
+#[options("/<_..>")]
+fn preflight() -> Status {
+    Status::NoContent
 }
 
-#[get("/new/<difficulty>")]
-fn new_diff_only(difficulty: String, clientid: ClientId) -> content::Json<String> {
-    create_response(req_captcha_newget(difficulty, client_id(clientid)))
+#[get("/admin/captcha/<id>?<reveal>")]
+async fn admin_inspect(ctx: RequestContext, _admin: Admin, id: String, reveal: Option<bool>) -> content::RawJson<String> {
+    create_response(ctx.scope(req_admin_inspect(id, reveal.unwrap_or(false))).await)
 }
 
-#[post("/solution/<id>/<solution>")]
-fn solution(id: String, solution: String, clientid: ClientId) -> content::Json<String> {
-    create_response(req_captcha_solution(id, solution, client_id(clientid)))
+#[delete("/admin/captcha/<id>")]
+async fn admin_revoke(ctx: RequestContext, _admin: Admin, id: String) -> content::RawJson<String> {
+    create_response(ctx.scope(req_admin_revoke(id)).await)
+}
+
+#[get("/admin/captchas?<client>&<cursor>&<count>")]
+async fn admin_list(ctx: RequestContext, _admin: Admin, client: Option<String>, cursor: Option<u64>, count: Option<usize>) -> content::RawJson<String> {
+    create_response(ctx.scope(req_admin_list(client, cursor, count)).await)
 }
 
+#[post("/admin/purge?<client>")]
+async fn admin_purge(ctx: RequestContext, _admin: Admin, client: String) -> content::RawJson<String> {
+    create_response(ctx.scope(req_admin_purge(client)).await)
+}
 
-fn main() {
-    env_logger::init();
+#[rocket::main]
+async fn main() {
+    let mut logger = env_logger::Builder::from_default_env();
+    if logging::json_enabled() {
+        logger.format(logging::format);
+    } else {
+        logger.format(logging::format_text);
+    }
+    logger.init();
 
     if !precondition_checks() {
         error!("Failed to start server.");
         return;
     }
 
+    match sites::load() {
+        Ok(n) => info!("Loaded [{}] sites.", n),
+        Err(e) => {
+            error!("Failed to load sites [{}].", e);
+            error!("Failed to start server.");
+            return;
+        }
+    }
+
+    match filters::load() {
+        Ok(n) => info!("Loaded [{}] filter profiles.", n),
+        Err(e) => {
+            error!("Failed to load filters [{}].", e);
+            error!("Failed to start server.");
+            return;
+        }
+    }
+
+    let settings = match tls::settings() {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Invalid TLS configuration [{}].", e);
+            error!("Failed to start server.");
+            return;
+        }
+    };
+
     info!("Starting service on port {} ...", PORT);
-    rocket::ignite()
-        .mount("/", routes![new, new_diff_only, solution])
-        .launch();
+    loop {
+        let reload = Arc::new(AtomicBool::new(false));
+        let r = rocket::custom(config(settings.as_ref()))
+            .mount("/", routes![new, new_diff_only, batch, solution, status, redeem, refresh])
+            .mount("/", routes![widget_js, widget_css, widget_new, widget_refresh, widget_audio, widget_site, preflight])
+            .mount("/", routes![admin_inspect, admin_revoke, admin_list, admin_purge])
+            .register("/", catchers![too_many_requests, unauthorized])
+            .attach(Cors)
+            .attach(RequestLog)
+            .attach(AdHoc::on_liftoff("Watch TLS files", {
+                let settings = settings.clone();
+                let reload = reload.clone();
+                move |rocket| Box::pin(async move {
+                    if let Some(settings) = settings {
+                        watch(settings, rocket.shutdown(), reload);
+                    }
+                })
+            }))
+            .attach(AdHoc::on_shutdown("Flush logs", |_| Box::pin(async {
+                info!("Shutting down ...");
+                shutdown::flush();
+            })))
+            .launch()
+            .await;
+        if let Err(e) = r {
+            error!("Server failed [{}].", e);
+            break;
+        }
+        if !reload.load(Ordering::SeqCst) {
+            break;
+        }
+        info!("Restarting service with the new TLS files ...");
+    }
+    shutdown::flush();
+}
+
+/// Shuts the server down so that it is restarted with the new files if the TLS files have
+/// changed. The files must not have changed for one interval, so that files which are still
+/// being written are not loaded. Invalid files are logged and the server keeps running with the
+/// files it has loaded.
+fn watch(settings: tls::Settings, shutdown: Shutdown, reload: Arc<AtomicBool>) {
+    let interval = tls::reload_interval();
+    if interval == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let files = settings.files();
+        let mut loaded = tls::modified(&files);
+        let mut last = loaded.clone();
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => return,
+                _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+            }
+            let now = tls::modified(&files);
+            if now != loaded && now == last && now.iter().all(Option::is_some) {
+                match tls::validate(&settings) {
+                    Ok(()) => {
+                        info!("TLS files changed.");
+                        reload.store(true, Ordering::SeqCst);
+                        shutdown.notify();
+                        return;
+                    },
+                    Err(e) => {
+                        // The files are checked again when they change the next time.
+                        error!("Ignoring changed TLS files [{}].", e);
+                        loaded = now.clone();
+                    }
+                }
+            }
+            last = now;
+        }
+    });
 }
@@ -0,0 +1,143 @@
+//! A ZIP archive which is written while it is sent.
+//!
+//! The entries are stored without compression as they are PNG images. As their sizes and
+//! checksums are known before they are written, the archive is written in one pass without
+//! seeking back, one chunk per entry.
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// Version 2.0 of the format, the first one with directories and stored entries.
+const VERSION: u16 = 20;
+
+/// Modification date of all entries, 1980-01-01 in MS-DOS format.
+const DATE: u16 = (1 << 5) | 1;
+
+/// Returns the chunks of a ZIP archive with the entries `(name, data)`. Archives are limited to
+/// 65535 entries and 4 GiB, which is far above the size of a batch.
+pub struct ZipStream {
+    entries: std::vec::IntoIter<(String, Vec<u8>)>,
+    offset: u32,
+    count: u16,
+    central: Vec<u8>,
+    finished: bool,
+}
+
+impl ZipStream {
+    pub fn new(entries: Vec<(String, Vec<u8>)>) -> ZipStream {
+        ZipStream {
+            entries: entries.into_iter(),
+            offset: 0,
+            count: 0,
+            central: vec![],
+            finished: false,
+        }
+    }
+
+    /// Returns the local header and the data of an entry and adds the entry to the central
+    /// directory.
+    fn entry(&mut self, name: &str, data: &[u8]) -> Vec<u8> {
+        let crc = crc32fast::hash(data);
+        let mut r = Vec::with_capacity(30 + name.len() + data.len());
+        u32le(&mut r, LOCAL_HEADER);
+        header(&mut r, name, crc, data.len() as u32);
+        r.extend_from_slice(name.as_bytes());
+        r.extend_from_slice(data);
+
+        u32le(&mut self.central, CENTRAL_HEADER);
+        u16le(&mut self.central, VERSION);
+        header(&mut self.central, name, crc, data.len() as u32);
+        u16le(&mut self.central, 0); // comment
+        u16le(&mut self.central, 0); // disk
+        u16le(&mut self.central, 0); // internal attributes
+        u32le(&mut self.central, 0); // external attributes
+        u32le(&mut self.central, self.offset);
+        self.central.extend_from_slice(name.as_bytes());
+
+        self.offset += r.len() as u32;
+        self.count += 1;
+        r
+    }
+
+    /// Returns the central directory and its end record.
+    fn end(&mut self) -> Vec<u8> {
+        let mut r = std::mem::take(&mut self.central);
+        let size = r.len() as u32;
+        u32le(&mut r, END_OF_CENTRAL_DIRECTORY);
+        u16le(&mut r, 0); // disk
+        u16le(&mut r, 0); // disk of the central directory
+        u16le(&mut r, self.count);
+        u16le(&mut r, self.count);
+        u32le(&mut r, size);
+        u32le(&mut r, self.offset);
+        u16le(&mut r, 0); // comment
+        r
+    }
+}
+
+impl Iterator for ZipStream {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.finished {
+            return None;
+        }
+        match self.entries.next() {
+            Some((name, data)) => Some(self.entry(&name, &data)),
+            None => {
+                self.finished = true;
+                Some(self.end())
+            }
+        }
+    }
+}
+
+/// Writes the fields which the local header and the central directory have in common.
+fn header(r: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    u16le(r, VERSION);
+    u16le(r, 0); // flags
+    u16le(r, 0); // stored
+    u16le(r, 0); // time
+    u16le(r, DATE);
+    u32le(r, crc);
+    u32le(r, size); // compressed
+    u32le(r, size);
+    u16le(r, name.len() as u16);
+    u16le(r, 0); // extra field
+}
+
+fn u16le(r: &mut Vec<u8>, v: u16) {
+    r.extend_from_slice(&v.to_le_bytes());
+}
+
+fn u32le(r: &mut Vec<u8>, v: u32) {
+    r.extend_from_slice(&v.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use crate::methods::archive::ZipStream;
+
+    #[test]
+    fn test_zip() {
+        let entries = vec![(String::from("a.png"), vec![1, 2, 3]), (String::from("b.json"), b"[]".to_vec())];
+        let chunks: Vec<Vec<u8>> = ZipStream::new(entries).collect();
+        assert_eq!(chunks.len(), 3);
+
+        let mut zip = ZipArchive::new(Cursor::new(chunks.concat())).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut data = vec![];
+        zip.by_name("a.png").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+        let mut s = String::new();
+        zip.by_name("b.json").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "[]");
+
+        assert_eq!(ZipArchive::new(Cursor::new(ZipStream::new(vec![]).collect::<Vec<_>>().concat())).unwrap().len(), 0);
+    }
+}
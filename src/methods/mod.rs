@@ -1,47 +1,262 @@
-use captcha::{Difficulty, gen};
-use validation::*;
-use persistence::{Persistence, Item, Error, build_item};
+mod archive;
 
-use uuid::Uuid;
+use std::env;
+use std::thread;
+
+use captcha::{Difficulty, Geometry, RngCaptcha};
+use image::RgbImage;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use crate::validation::*;
+use crate::persistence::{Persistence, Item, Error, TombstoneState, build_item};
+use crate::animation;
+use crate::audio;
+use crate::filters;
+use crate::ocr;
+use crate::pow;
+use crate::sites;
+use crate::theme::{self, encode_png, Theme, CHARS};
+pub use self::archive::ZipStream;
+pub use rust_captcha_types::{CaptchaSolutionResponse, CaptchaState, CaptchaStatus, NewAnimationResponse, NewCaptchaResponse, NewPowResponse, RedeemResponse, Verdict};
+
+use uuid::{Builder, Uuid, Variant, Version};
 use base64::encode;
-use serde_json;
-use time;
 
 pub type CaptchaNewResult = Result<CaptchaNewDetails, CaptchaError>;
 pub type CaptchaSolutionResult = Result<CaptchaSolutionDetails, CaptchaError>;
 
+#[derive(Debug, Clone)]
 pub struct CaptchaSolutionDetails {
-    json: String,
     uuid: String,
-    csr: CaptchaSolutionResponse,
+    verdict: Verdict,
 }
 
 impl CaptchaSolutionDetails {
-    pub fn as_json(&self) -> String {
-        self.json.clone()
-    }
-
     pub fn uuid(&self) -> String {
         self.uuid.clone()
     }
 
-    pub fn csr(&self) -> CaptchaSolutionResponse {
-        self.csr.clone()
+    pub fn verdict(&self) -> Verdict {
+        self.verdict
+    }
+
+    pub fn accepted(&self) -> bool {
+        self.verdict == Verdict::Accepted
+    }
+
+    /// Number of tries left after this check.
+    pub fn trials_left(&self) -> usize {
+        self.verdict.tries_left()
     }
+
+    /// Returns the response which is sent to clients.
+    pub fn response(&self) -> CaptchaSolutionResponse {
+        CaptchaSolutionResponse::new(self.verdict)
+    }
+}
+
+/// The challenge of a new CAPTCHA.
+#[derive(Debug, Clone)]
+pub enum NewChallenge {
+    /// An image CAPTCHA with its solution and the image in PNG format.
+    Image { solution: String, png: Vec<u8> },
+    /// A proof-of-work challenge.
+    ProofOfWork { nonce: String, bits: u32 },
+    /// An animated CAPTCHA with its solution and the animation in GIF format.
+    Animation { solution: String, gif: Vec<u8> },
 }
 
+/// The response to a request for a new CAPTCHA.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum NewResponse {
+    Image(NewCaptchaResponse),
+    ProofOfWork(NewPowResponse),
+    Animation(NewAnimationResponse),
+}
+
+/// The sender of a request for the status or the token of a CAPTCHA.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Caller {
+    /// Code which runs within the service or has access to Redis, e.g. the request guard. It is
+    /// not checked.
+    Trusted,
+    /// A backend with the secret of its site, if it has sent one (see `sites::authorized`).
+    Backend(Option<String>),
+    /// A frontend with its client id. It can query the status of its own CAPTCHAs, e.g. to show
+    /// the number of tries left, but cannot tell whether they have been solved or redeem them.
+    Frontend(String),
+}
+
+#[derive(Debug, Clone)]
 pub struct CaptchaNewDetails {
-    json: String,
     uuid: String,
+    challenge: NewChallenge,
+    tries_left: Option<usize>,
+    expires: Option<i64>,
 }
 
 impl CaptchaNewDetails {
-    pub fn as_json(&self) -> String {
-        self.json.clone()
+    pub fn uuid(&self) -> String {
+        self.uuid.clone()
+    }
+
+    pub fn challenge(&self) -> &NewChallenge {
+        &self.challenge
+    }
+
+    /// Returns the solution of an image or animated CAPTCHA.
+    pub fn solution(&self) -> Option<&str> {
+        match self.challenge {
+            NewChallenge::Image { ref solution, .. } |
+            NewChallenge::Animation { ref solution, .. } => Some(solution),
+            NewChallenge::ProofOfWork { .. } => None
+        }
+    }
+
+    /// Returns the image of an image CAPTCHA.
+    pub fn png(&self) -> Option<&[u8]> {
+        match self.challenge {
+            NewChallenge::Image { ref png, .. } => Some(png),
+            _ => None
+        }
+    }
+
+    /// Returns the animation of an animated CAPTCHA.
+    pub fn gif(&self) -> Option<&[u8]> {
+        match self.challenge {
+            NewChallenge::Animation { ref gif, .. } => Some(gif),
+            _ => None
+        }
     }
 
+    /// Number of tries. `None` if the CAPTCHA has not been persisted.
+    pub fn tries_left(&self) -> Option<usize> {
+        self.tries_left
+    }
+
+    /// Expiration time in seconds since the epoch. `None` if the CAPTCHA has not been persisted.
+    pub fn expires(&self) -> Option<i64> {
+        self.expires
+    }
+
+    /// Returns the response which is sent to clients. The solution of an image CAPTCHA is only
+    /// included if `reveal` is true.
+    pub fn response(&self, reveal: bool) -> NewResponse {
+        match self.challenge {
+            NewChallenge::Image { ref solution, ref png } => NewResponse::Image(NewCaptchaResponse {
+                id: self.uuid.clone(),
+                png: encode(png),
+                solution: if reveal { solution.clone() } else { String::new() },
+            }),
+            NewChallenge::ProofOfWork { ref nonce, bits } => NewResponse::ProofOfWork(NewPowResponse {
+                id: self.uuid.clone(),
+                nonce: nonce.clone(),
+                bits,
+            }),
+            NewChallenge::Animation { ref solution, ref gif } => NewResponse::Animation(NewAnimationResponse {
+                id: self.uuid.clone(),
+                gif: encode(gif),
+                solution: if reveal { solution.clone() } else { String::new() },
+            })
+        }
+    }
+}
+
+/// Prefix of the names of animated CAPTCHAs, e.g. `animated-hard`.
+pub const ANIMATED: &str = "animated-";
+
+/// The type of a challenge.
+pub enum Challenge {
+    /// An image CAPTCHA with the given difficulty.
+    Image(Difficulty),
+    /// A proof-of-work challenge which requires the given number of leading zero bits.
+    ProofOfWork(u32),
+    /// An animated CAPTCHA with the given difficulty.
+    Animated(Difficulty),
+}
+
+impl Challenge {
+    pub fn name(&self) -> String {
+        match *self {
+            Challenge::Image(Difficulty::Easy)      => String::from("easy"),
+            Challenge::Image(Difficulty::Medium)    => String::from("medium"),
+            Challenge::Image(Difficulty::Hard)      => String::from("hard"),
+            Challenge::ProofOfWork(bits)            => format!("pow-{}", bits),
+            Challenge::Animated(Difficulty::Easy)   => format!("{}easy", ANIMATED),
+            Challenge::Animated(Difficulty::Medium) => format!("{}medium", ANIMATED),
+            Challenge::Animated(Difficulty::Hard)   => format!("{}hard", ANIMATED),
+        }
+    }
+}
+
+/// A CAPTCHA of a batch.
+pub struct BatchCaptcha {
+    id: String,
+    solution: String,
+    png: Vec<u8>,
+}
+
+impl BatchCaptcha {
     pub fn uuid(&self) -> String {
-        self.uuid.clone()
+        self.id.clone()
+    }
+
+    pub fn solution(&self) -> String {
+        self.solution.clone()
+    }
+
+    pub fn png(&self) -> &[u8] {
+        &self.png
+    }
+}
+
+/// A batch of CAPTCHAs created with `captcha_batch`.
+pub struct CaptchaBatch {
+    captchas: Vec<BatchCaptcha>,
+}
+
+impl CaptchaBatch {
+    pub fn len(&self) -> usize {
+        self.captchas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captchas.is_empty()
+    }
+
+    pub fn captchas(&self) -> &[BatchCaptcha] {
+        &self.captchas
+    }
+
+    /// Returns the CAPTCHAs in the same format as a new CAPTCHA.
+    pub fn response(&self) -> Vec<NewCaptchaResponse> {
+        self.captchas.iter()
+            .map(|c| NewCaptchaResponse {
+                id: c.id.clone(),
+                png: encode(&c.png),
+                solution: c.solution.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the CAPTCHAs as ZIP archive which contains an image `<id>.png` for each CAPTCHA
+    /// and a file `manifest.json` with the ids and solutions. The archive is written while it is
+    /// read.
+    pub fn into_zip(self) -> Result<ZipStream, CaptchaError> {
+        let manifest = self.captchas.iter()
+            .map(|c| BatchManifestEntry {
+                id: c.id.clone(),
+                solution: c.solution.clone(),
+                file: format!("{}.png", c.id),
+            })
+            .collect::<Vec<_>>();
+        let manifest = serde_json::to_vec(&manifest).map_err(|_| CaptchaError::ToJson)?;
+        let mut entries = self.captchas.into_iter()
+            .map(|c| (format!("{}.png", c.id), c.png))
+            .collect::<Vec<_>>();
+        entries.push((String::from("manifest.json"), manifest));
+        Ok(ZipStream::new(entries))
     }
 }
 
@@ -53,85 +268,383 @@ pub enum CaptchaError {
     ToJson,
     Persist,
     NotFound,
+    TooManyRefreshes,
+    Unauthorized,
     Unexpected
 }
 
-pub fn captcha_newget(difficulty: String) -> CaptchaNewResult {
-    // TODO this code is in parts duplicated from captcha_new
-
+/// Creates a new CAPTCHA that is not persisted.
+///
+/// The defaults, limits and theme of the site `clientid` are applied like by `captcha_new`.
+/// If a seed is given, the same seed and difficulty always create the same id, solution and image.
+pub async fn captcha_newget(difficulty: String, clientid: String, seed: Option<u64>) -> CaptchaNewResult {
+    let (difficulty, _, _) = sites::apply(&clientid, difficulty, sites::DEFAULT.into(), sites::DEFAULT.into())?;
     let d = validate_difficulty(difficulty)?;
+    let theme = sites::get(&clientid).and_then(|s| s.theme());
 
-    let uuid = create_uuid();
-    let (solution, png) = create_captcha(d)?;
+    let (uuid, solution, png) = blocking(move || {
+        let mut rng = seed.map(StdRng::seed_from_u64);
+        let uuid = create_uuid(rng.as_mut());
+        let (solution, png) = create_captcha(d, rng.as_mut(), theme.as_ref())?;
+        Ok((uuid, solution, png))
+    }).await?;
 
-    let c = NewCaptchaResponse {
-        id: uuid.clone(),
-        png: encode(&png),
-        solution: solution.clone(),
-    };
+    Ok(CaptchaNewDetails {
+        uuid,
+        challenge: NewChallenge::Image { solution, png },
+        tries_left: None,
+        expires: None,
+    })
+}
 
-    let captcha = CaptchaNewDetails {
-        json: serde_json::to_string(&c).map_err(|_| CaptchaError::ToJson)?,
-        uuid: uuid.clone(),
-    };
+/// Creates a new CAPTCHA that is persisted.
+///
+/// If a seed is given, the same seed and difficulty always create the same id, solution and image.
+pub async fn captcha_new(difficulty: String, max_tries: String, ttl: String, clientid: String, seed: Option<u64>) -> CaptchaNewResult {
+    create_persisted(difficulty, max_tries, ttl, clientid, seed, false).await
+}
 
-    Ok(captcha)
+/// Creates a new CAPTCHA that is persisted and whose id is a verification token. Once the
+/// CAPTCHA has been solved, the token can be redeemed with `captcha_redeem`.
+///
+/// The solution must not be revealed to the client (see `CaptchaNewDetails::response`).
+/// Otherwise, anyone could solve the CAPTCHA and obtain a valid token.
+pub async fn captcha_new_token(difficulty: String, max_tries: String, ttl: String, clientid: String) -> CaptchaNewResult {
+    create_persisted(difficulty, max_tries, ttl, clientid, None, true).await
 }
 
-pub fn captcha_new(difficulty: String, max_tries: String, ttl: String) -> CaptchaNewResult {
+async fn create_persisted(difficulty: String, max_tries: String, ttl: String, clientid: String, seed: Option<u64>, token: bool) -> CaptchaNewResult {
 
-    let d = validate_difficulty(difficulty)?;
-    let x = validate_tries(max_tries)?;
-    let t = validate_ttl(ttl)?;
+    let (difficulty, mut x, t) = sites::apply(&clientid, difficulty, max_tries, ttl)?;
+    let c = validate_challenge(difficulty, pow::bits_for(&clientid))?;
 
-    let uuid = create_uuid();
-    let (solution, png) = create_captcha(d)?;
+    let mut rng = seed.map(StdRng::seed_from_u64);
+    let uuid = create_uuid(rng.as_mut());
+    let name = c.name();
 
-    let c = NewCaptchaResponse {
-        id: uuid.clone(),
-        png: encode(&png),
-        solution: solution.clone(),
+    let challenge = match c {
+        Challenge::Image(d) => {
+            let theme = sites::get(&clientid).and_then(|s| s.theme());
+            let (solution, png) = blocking(move || create_captcha(d, rng.as_mut(), theme.as_ref())).await?;
+            NewChallenge::Image { solution, png }
+        },
+        Challenge::Animated(d) => {
+            let theme = sites::get(&clientid).and_then(|s| s.theme());
+            let (solution, gif) = blocking(move || create_animated_captcha(d, rng.as_mut(), theme.as_ref())).await?;
+            NewChallenge::Animation { solution, gif }
+        },
+        Challenge::ProofOfWork(bits) => {
+            // A proof-of-work challenge can be used only once.
+            x = x.min(1);
+            let nonce = match rng.as_mut() {
+                Some(r) => create_uuid(Some(r)).replace("-", ""),
+                None    => pow::create_nonce()
+            };
+            NewChallenge::ProofOfWork { nonce, bits }
+        }
     };
 
-    let captcha = CaptchaNewDetails {
-        json: serde_json::to_string(&c).map_err(|_| CaptchaError::ToJson)?,
-        uuid: uuid.clone(),
+    // The solution of a proof-of-work challenge is checked against its nonce.
+    let solution = match challenge {
+        NewChallenge::Image { ref solution, .. } |
+        NewChallenge::Animation { ref solution, .. } => solution.clone(),
+        NewChallenge::ProofOfWork { ref nonce, .. } => nonce.clone()
     };
 
     let item = build_item()
-        .uuid(uuid)
+        .uuid(uuid.clone())
         .solution(solution)
         .tries_left(x)
         .ttl(t)
+        .challenge(name)
+        .client(clientid)
+        .token(token)
         .item()
         .map_err(|_| CaptchaError::Unexpected)?;
 
-    Persistence::set(item)
+    let captcha = CaptchaNewDetails {
+        uuid,
+        challenge,
+        tries_left: Some(item.tries_left()),
+        expires: Some(item.expires()),
+    };
+
+    Persistence::set(item).await
         .map(|_| captcha)
         .map_err(|_| CaptchaError::Persist)
 }
 
-pub fn captcha_solution(id: String, solution: String) -> CaptchaSolutionResult {
+/// Creates `count` persisted image CAPTCHAs. The images are generated in parallel and all
+/// CAPTCHAs are stored with a single request.
+pub async fn captcha_batch(difficulty: String, count: String, max_tries: String, ttl: String, clientid: String) -> Result<CaptchaBatch, CaptchaError> {
+
+    let (difficulty, x, t) = sites::apply(&clientid, difficulty, max_tries, ttl)?;
+    let name = Challenge::Image(validate_difficulty(difficulty)?).name();
+    let n = validate_batch_size(count, max_batch_size())?;
+
+    let d = name.clone();
+    let theme = sites::get(&clientid).and_then(|s| s.theme());
+    let captchas = blocking(move || create_captchas(&d, n, None, theme)).await?;
+
+    let items = captchas.iter()
+        .map(|c| build_item()
+            .uuid(&c.id)
+            .solution(&c.solution)
+            .tries_left(x)
+            .ttl(t)
+            .challenge(&name)
+            .client(&clientid)
+            .item())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| CaptchaError::Unexpected)?;
+
+    Persistence::set_many(&items).await
+        .map(|_| CaptchaBatch { captchas })
+        .map_err(|_| CaptchaError::Persist)
+}
+
+/// Creates `count` image CAPTCHAs that are not persisted. The images are generated in parallel.
+///
+/// If a seed is given, the i-th CAPTCHA is created with the seed `seed + i`.
+pub async fn captcha_generate(difficulty: String, count: usize, seed: Option<u64>) -> Result<CaptchaBatch, CaptchaError> {
+    let name = Challenge::Image(validate_difficulty(difficulty)?).name();
+    blocking(move || create_captchas(&name, count, seed, None)).await
+        .map(|captchas| CaptchaBatch { captchas })
+}
+
+/// Measures how well image CAPTCHAs resist the baseline OCR solver of `ocr`.
+///
+/// For each difficulty and each set of filters of its profile (see `ocr::filter_sets`), `count`
+/// CAPTCHAs are created with the seeds `seed..seed + count` and solved. Hence all sets of filters
+/// of a difficulty are evaluated with the same characters. The CAPTCHAs are created like those
+/// of `create_captcha` without a theme, so the set `all` measures the CAPTCHAs which are served.
+pub async fn captcha_benchmark(difficulties: Vec<String>, count: usize, seed: u64) -> Result<Vec<ocr::Score>, CaptchaError> {
+    let jobs = difficulties.into_iter()
+        .map(|d| {
+            validate_difficulty(d.clone())?;
+            Ok(ocr::filter_sets(&filters::profile(&d)).into_iter().map(move |(f, p)| (d.clone(), f, p)))
+        })
+        .collect::<Result<Vec<_>, CaptchaError>>()?;
+
+    blocking(move || {
+        let handles = jobs.into_iter()
+            .flatten()
+            .map(|(d, f, profile)| thread::spawn(move || {
+                let solver = ocr::Solver::default();
+                let theme = Theme::default();
+                let mut score = ocr::Score::new(&d, &f);
+                for i in 0..count {
+                    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+                    let (solution, png) = render(&validate_difficulty(d.clone())?, &mut rng, &theme, &profile)
+                        .ok_or(CaptchaError::CaptchaGeneration)?;
+                    score.add(&solution, solver.solve(&png, solution.chars().count()).as_deref());
+                }
+                Ok(score)
+            }))
+            .collect::<Vec<_>>();
+        handles.into_iter()
+            .map(|h| h.join().map_err(|_| CaptchaError::CaptchaGeneration)?)
+            .collect()
+    }).await
+}
+
+/// Checks the solution of a CAPTCHA.
+///
+/// `clientid` is the client which sends the solution. The CAPTCHA of a registered site can only
+/// be solved by the site. `None` skips this check, e.g. for backends which access Redis directly.
+pub async fn captcha_solution(id: String, solution: String, clientid: Option<String>) -> CaptchaSolutionResult {
 
     let i = validate_id(id)?;
     let s = validate_solution(solution)?;
 
-    let csr = Persistence::get(i.to_hyphenated().to_string())
-        .map_err(persistence_error_mapping)
-        .and_then(|item| check(s, item))?;
-
-    let json = serde_json::to_string(&csr).map_err(|_| CaptchaError::ToJson)?;
+    let uuid = i.to_hyphenated().to_string();
+    let verdict = match Persistence::get(uuid.clone()).await {
+        Ok(item) => {
+            if let Some(c) = clientid {
+                if c != item.client() && sites::get(&item.client()).is_some() {
+                    return Err(CaptchaError::Unauthorized);
+                }
+            }
+            check(s, item).await?
+        },
+        // The tombstone tells why the CAPTCHA does not exist.
+        Err(Error::NotFound) => match Persistence::tombstone(uuid.clone()).await {
+            Ok(t) => match t.state() {
+                TombstoneState::Solved  => Verdict::AlreadyUsed,
+                TombstoneState::Expired => Verdict::Expired
+            },
+            Err(Error::NotFound) => Verdict::NotFound,
+            Err(e) => return Err(persistence_error_mapping(e))
+        },
+        Err(e) => return Err(persistence_error_mapping(e))
+    };
 
     Ok(CaptchaSolutionDetails {
-        json: json,
-        uuid: i.to_hyphenated().to_string(),
-        csr: csr
+        uuid,
+        verdict,
     })
 }
 
+/// Replaces an image or animated CAPTCHA by a new one with the same type, expiration time and
+/// number of tries left. The old CAPTCHA is removed.
+///
+/// A CAPTCHA can only be refreshed by the client which has created it. `None` skips this check.
+pub async fn captcha_refresh(id: String, clientid: Option<String>) -> CaptchaNewResult {
+
+    let i = validate_id(id)?;
+
+    let old = Persistence::get(i.to_hyphenated().to_string()).await
+        .map_err(persistence_error_mapping)?;
+
+    if clientid.is_some_and(|c| c != old.client()) {
+        return Err(CaptchaError::Unauthorized);
+    }
+
+    if old.tries_left() == 0 || old.expires() <= time::now().to_timespec().sec {
+        return Err(CaptchaError::InvalidParameters);
+    }
+    if old.refreshes() >= max_refreshes() {
+        return Err(CaptchaError::TooManyRefreshes);
+    }
+
+    let uuid = create_uuid(None);
+    let theme = sites::get(&old.client()).and_then(|s| s.theme());
+    let (solution, challenge) = match validate_challenge(old.challenge(), 0) {
+        Ok(Challenge::Image(d)) => {
+            let (solution, png) = blocking(move || create_captcha(d, None, theme.as_ref())).await?;
+            (solution.clone(), NewChallenge::Image { solution, png })
+        },
+        Ok(Challenge::Animated(d)) => {
+            let (solution, gif) = blocking(move || create_animated_captcha(d, None, theme.as_ref())).await?;
+            (solution.clone(), NewChallenge::Animation { solution, gif })
+        },
+        _ => return Err(CaptchaError::InvalidParameters)
+    };
+
+    let item = build_item()
+        .uuid(uuid.clone())
+        .solution(solution)
+        .tries_left(old.tries_left())
+        .expires(time::at(time::Timespec::new(old.expires(), 0)))
+        .challenge(old.challenge())
+        .client(old.client())
+        .refreshes(old.refreshes() + 1)
+        .token(old.token())
+        .item()
+        .map_err(|_| CaptchaError::Unexpected)?;
+
+    let captcha = CaptchaNewDetails {
+        uuid,
+        challenge,
+        tries_left: Some(item.tries_left()),
+        expires: Some(item.expires()),
+    };
+
+    Persistence::replace(&old, &item).await.map_err(persistence_error_mapping)?;
+
+    Ok(captcha)
+}
+
+/// Returns an audio in WAV format which reads out the solution of a pending image or animated
+/// CAPTCHA. Proof-of-work challenges have no audio.
+pub async fn captcha_audio(id: String) -> Result<Vec<u8>, CaptchaError> {
+
+    let i = validate_id(id)?;
+
+    let item = Persistence::get(i.to_hyphenated().to_string()).await
+        .map_err(persistence_error_mapping)?;
+
+    if item.tries_left() == 0 || item.expires() <= time::now().to_timespec().sec {
+        return Err(CaptchaError::InvalidParameters);
+    }
+    match validate_challenge(item.challenge(), 0) {
+        Ok(Challenge::Image(_)) | Ok(Challenge::Animated(_)) => {},
+        _ => return Err(CaptchaError::InvalidParameters)
+    }
+
+    let solution = item.solution();
+    blocking(move || audio::wav(&solution, &mut StdRng::from_entropy()).ok_or(CaptchaError::CaptchaGeneration)).await
+}
+
+/// Redeems the token of a solved CAPTCHA. The token is the id of a CAPTCHA which has been created
+/// with `captcha_new_token`. Returns `false` if the CAPTCHA has not been solved, if its solution
+/// has been revealed to the client or if the token has already been redeemed.
+///
+/// The token of a CAPTCHA of a registered site can only be redeemed with the secret of the site.
+pub async fn captcha_redeem(id: String, caller: Caller) -> Result<RedeemResponse, CaptchaError> {
+
+    let i = validate_id(id)?.to_hyphenated().to_string();
+
+    let redeemed = match Persistence::tombstone(i.clone()).await {
+        Ok(t) => {
+            if !authorize(&t.client(), &caller)? {
+                return Err(CaptchaError::Unauthorized);
+            }
+            match t.state() {
+                TombstoneState::Solved if t.token() => Persistence::redeem(i.clone()).await
+                    .map_err(persistence_error_mapping)?,
+                _ => false
+            }
+        },
+        Err(Error::NotFound) => false,
+        Err(e) => return Err(persistence_error_mapping(e))
+    };
+    Ok(RedeemResponse::new(i, redeemed))
+}
+
+/// Returns the state of a CAPTCHA without consuming a try.
+///
+/// The status of a CAPTCHA of a registered site can only be queried with the secret of the
+/// site. Frontends can query the status of their own CAPTCHAs unless they have been solved.
+pub async fn captcha_status(id: String, caller: Caller) -> Result<CaptchaStatus, CaptchaError> {
+
+    let i = validate_id(id)?.to_hyphenated().to_string();
+    let now = time::now().to_timespec().sec;
+
+    match Persistence::get(i.clone()).await {
+        Ok(item) => authorize(&item.client(), &caller).map(|_| CaptchaStatus::new(
+            i,
+            match item.tries_left() {
+                _ if item.expires() <= now => CaptchaState::Expired,
+                0 => CaptchaState::Exhausted,
+                _ => CaptchaState::Pending
+            },
+            item.tries_left(),
+            item.expires(),
+        )),
+        Err(Error::NotFound) => Persistence::tombstone(i.clone()).await
+            .map_err(persistence_error_mapping)
+            .and_then(|t| match (authorize(&t.client(), &caller)?, t.state()) {
+                (false, TombstoneState::Solved) => Err(CaptchaError::Unauthorized),
+                _ => Ok(t)
+            })
+            .map(|t| CaptchaStatus::new(
+                i,
+                match t.state() {
+                    TombstoneState::Solved  => CaptchaState::Solved,
+                    TombstoneState::Expired => CaptchaState::Expired
+                },
+                0,
+                t.expires(),
+            )),
+        Err(e) => Err(persistence_error_mapping(e))
+    }
+}
+
+/// Checks whether `caller` may access the CAPTCHAs of the client `client`. Returns `false` if
+/// the caller is the frontend of the client, which may only access the pending state.
+fn authorize(client: &str, caller: &Caller) -> Result<bool, CaptchaError> {
+    match caller {
+        Caller::Trusted => Ok(true),
+        Caller::Backend(secret) if sites::authorized(client, secret.as_deref()) => Ok(true),
+        Caller::Frontend(c) if c == client => Ok(false),
+        _ => Err(CaptchaError::Unauthorized)
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 
-fn persistence_error_mapping(e: Error) -> CaptchaError {
+pub(crate) fn persistence_error_mapping(e: Error) -> CaptchaError {
     match e {
         Error::Connection |
         Error::NoLocation  => CaptchaError::Persist,
@@ -140,65 +653,402 @@ fn persistence_error_mapping(e: Error) -> CaptchaError {
     }
 }
 
-fn check_solution(user_solution: String, item: Item) -> CaptchaSolutionResponse {
+async fn check_solution(user_solution: String, item: Item) -> Verdict {
     if item.solution() == user_solution {
-        Persistence::del(item.uuid());
-        CaptchaSolutionResponse::accept()
+        Persistence::solved(&item).await.ok();
+        Verdict::Accepted
     } else {
-        let t = time::now().to_timespec().sec;
-        if item.expires() > t {
-            Persistence::set(item.dec_tries_left()).ok();
-        } else {
-            Persistence::del(item.uuid());
-        };
-        CaptchaSolutionResponse::reject("incorrect", item.tries_left() - 1)
+        Persistence::set(item.dec_tries_left()).await.ok();
+        Verdict::Incorrect { tries_left: item.tries_left() - 1 }
     }
 }
 
-fn check(user_solution: String, item: Item) -> Result<CaptchaSolutionResponse, CaptchaError> {
-   Ok(match item.tries_left() {
-        0 => CaptchaSolutionResponse::reject("too many trials", 0),
-        _ => check_solution(user_solution, item)
-    })
+async fn check_pow(user_solution: String, item: Item, bits: u32) -> Verdict {
+    // Proof-of-work challenges are single use. Each attempt consumes the challenge.
+    if pow::verify(&item.solution(), bits, &user_solution) {
+        Persistence::solved(&item).await.ok();
+        Verdict::Accepted
+    } else {
+        Persistence::set(item.dec_tries_left()).await.ok();
+        Verdict::Incorrect { tries_left: 0 }
+    }
+}
+
+async fn check(user_solution: String, item: Item) -> Result<Verdict, CaptchaError> {
+    // Redis removes an item when it expires. The tombstone outlives it and records the expiry.
+    if item.expires() <= time::now().to_timespec().sec {
+        Persistence::del(&item).await;
+        return Ok(Verdict::Expired);
+    }
+    if item.tries_left() == 0 {
+        return Ok(Verdict::Exhausted);
+    }
+    match validate_challenge(item.challenge(), 0) {
+        Ok(Challenge::ProofOfWork(bits)) => Ok(check_pow(user_solution, item, bits).await),
+        // The solution of a proof-of-work challenge is its nonce, hence it must not be compared
+        // with the solution.
+        _ if item.challenge().starts_with("pow") => {
+            error!("Invalid challenge [{}] of CAPTCHA [{}].", item.challenge(), item.uuid());
+            Err(CaptchaError::Unexpected)
+        },
+        _ => Ok(check_solution(user_solution, item).await)
+    }
 }
 
-#[derive(Serialize, Clone)]
-pub struct CaptchaSolutionResponse {
+#[derive(Serialize)]
+struct BatchManifestEntry {
+    id: String,
     solution: String,
-    trials_left: usize
+    file: String,
 }
 
-impl CaptchaSolutionResponse {
-    pub fn reject(reason: &str, trials_left: usize) -> CaptchaSolutionResponse {
-        CaptchaSolutionResponse {
-            solution: String::from(reason),
-            trials_left
-        }
+/// Maximum number of times a CAPTCHA can be refreshed. Configured via the environment variable
+/// `REFRESH_LIMIT` (default: 3).
+fn max_refreshes() -> usize {
+    env::var("REFRESH_LIMIT").ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .unwrap_or(3)
+}
+
+/// Maximum number of CAPTCHAs in a batch. Configured via the environment variable `BATCH_MAX`
+/// (default: 100).
+fn max_batch_size() -> usize {
+    env::var("BATCH_MAX").ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .unwrap_or(100)
+}
+
+/// Number of threads used to create a batch. Configured via the environment variable
+/// `BATCH_THREADS` (default: 4).
+fn batch_threads() -> usize {
+    env::var("BATCH_THREADS").ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(4)
+}
+
+/// Runs CPU bound work like the generation of images on the blocking thread pool so that the
+/// threads which serve requests are not blocked.
+async fn blocking<T, F>(f: F) -> Result<T, CaptchaError>
+    where F: FnOnce() -> Result<T, CaptchaError> + Send + 'static, T: Send + 'static {
+    tokio::task::spawn_blocking(f).await.map_err(|_| CaptchaError::CaptchaGeneration)?
+}
+
+fn create_captchas(difficulty: &str, n: usize, seed: Option<u64>, theme: Option<Theme>) -> Result<Vec<BatchCaptcha>, CaptchaError> {
+    let threads = batch_threads().min(n).max(1);
+    let handles = (0..threads)
+        .map(|i| {
+            let d = difficulty.to_string();
+            let theme = theme.clone();
+            // The thread creates the CAPTCHAs start..start + k.
+            let start = i * (n / threads) + i.min(n % threads);
+            let k = n / threads + if i < n % threads { 1 } else { 0 };
+            thread::spawn(move || (start..start + k)
+                .map(|j| {
+                    let mut rng = seed.map(|s| StdRng::seed_from_u64(s.wrapping_add(j as u64)));
+                    let id = create_uuid(rng.as_mut());
+                    let (solution, png) = create_captcha(validate_difficulty(d.clone())?, rng.as_mut(), theme.as_ref())?;
+                    Ok(BatchCaptcha { id, solution, png })
+                })
+                .collect::<Result<Vec<_>, CaptchaError>>())
+        })
+        .collect::<Vec<_>>();
+
+    let mut r = Vec::with_capacity(n);
+    for h in handles {
+        r.extend(h.join().map_err(|_| CaptchaError::CaptchaGeneration)??);
     }
+    Ok(r)
+}
 
-    pub fn accept() -> CaptchaSolutionResponse {
-        CaptchaSolutionResponse {
-            solution: String::from("accepted"),
-            trials_left: 0
+/// Creates a random UUID. If a random number generator is given, the UUID is taken from it so
+/// that seeded generators create the same UUIDs.
+fn create_uuid(rng: Option<&mut StdRng>) -> String {
+    match rng {
+        None => Uuid::new_v4(),
+        Some(r) => {
+            let mut bytes = [0u8; 16];
+            r.fill_bytes(&mut bytes);
+            Builder::from_bytes(bytes)
+                .set_variant(Variant::RFC4122)
+                .set_version(Version::Random)
+                .build()
         }
-    }
+    }.to_hyphenated().to_string()
+}
 
-    pub fn result(&self) -> String {
-        self.solution.clone()
-    }
+/// Creates an image CAPTCHA. The theme sets the size, the colors and the font of the image and
+/// the filters of the difficulty distort it (see `filters`).
+fn create_captcha(d: Difficulty, rng: Option<&mut StdRng>, theme: Option<&Theme>) -> Result<(String, Vec<u8>), CaptchaError> {
+    let mut entropy = StdRng::from_entropy();
+    let rng = rng.unwrap_or(&mut entropy);
+    let default = Theme::default();
+    render(&d, rng, theme.unwrap_or(&default), &profile(&d)).ok_or(CaptchaError::CaptchaGeneration)
 }
 
-#[derive(Serialize)]
-struct NewCaptchaResponse {
-    id: String,
-    png: String,
-    solution: String,
+// All filters take their randomness from the generator of the CAPTCHA so that the image depends
+// on the seed only.
+fn render(d: &Difficulty, rng: &mut StdRng, theme: &Theme, profile: &filters::Profile) -> Option<(String, Vec<u8>)> {
+    let (solution, mut img) = draw_chars(d, rng, theme)?;
+    profile.apply(&mut img, theme, rng);
+    Some((solution, encode_png(img)?))
 }
 
-fn create_uuid() -> String {
-    Uuid::new_v4().to_hyphenated().to_string()
+/// Creates an animated CAPTCHA. The filters of the difficulty are applied to each frame.
+fn create_animated_captcha(d: Difficulty, rng: Option<&mut StdRng>, theme: Option<&Theme>) -> Result<(String, Vec<u8>), CaptchaError> {
+    let mut entropy = StdRng::from_entropy();
+    let rng = rng.unwrap_or(&mut entropy);
+    let default = Theme::default();
+    let theme = theme.unwrap_or(&default);
+    draw_chars(&d, rng, theme)
+        .and_then(|(solution, img)| {
+            let gif = animation::animate(&img, solution.chars().count(), &profile(&d), theme, rng)?;
+            Some((solution, gif))
+        })
+        .ok_or(CaptchaError::CaptchaGeneration)
 }
 
-fn create_captcha(d: Difficulty) -> Result<(String, Vec<u8>), CaptchaError> {
-    gen(d).as_tuple().ok_or(CaptchaError::CaptchaGeneration)
+/// Draws the characters of a CAPTCHA in black on white with the size and the font of the theme.
+fn draw_chars(d: &Difficulty, rng: &mut StdRng, theme: &Theme) -> Option<(String, RgbImage)> {
+    let n = match d {
+        Difficulty::Easy   => 4,
+        Difficulty::Medium => 5,
+        Difficulty::Hard   => 6,
+    };
+    match theme.font() {
+        Some(f) => {
+            let chars: Vec<char> = CHARS.chars().collect();
+            let solution: String = (0..n).map(|_| chars[rng.gen_range(0..chars.len())]).collect();
+            let img = f.draw(&solution, theme.width(), theme.height())?;
+            Some((solution, img))
+        },
+        None => {
+            // The characters of the font are in random order, hence they are set in a fixed order
+            // so that seeded CAPTCHAs depend on the seed only.
+            let chars: Vec<char> = CHARS.chars().collect();
+            let mut c = RngCaptcha::from_rng(rng);
+            c.set_chars(&chars).add_chars(n);
+            // The text is cropped and centered here because `view` fails if the text is much
+            // narrower than the image.
+            let a = c.text_area();
+            let (solution, png) = c.extract(Geometry::new(a.left, a.right + 1, a.top, a.bottom + 1)).as_tuple()?;
+            let text = image::load_from_memory(&png).ok()?.to_luma8();
+            Some((solution, theme::place(&[&text], theme.width(), theme.height())))
+        }
+    }
+}
+
+fn profile(d: &Difficulty) -> filters::Profile {
+    filters::profile(match d {
+        Difficulty::Easy   => "easy",
+        Difficulty::Medium => "medium",
+        Difficulty::Hard   => "hard",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use captcha::Difficulty;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::methods::{create_uuid, create_captcha, captcha_audio, captcha_benchmark, captcha_generate, captcha_new, captcha_new_token, captcha_redeem, captcha_refresh, captcha_solution};
+    use crate::methods::{Caller, CaptchaError, CaptchaNewDetails, CaptchaSolutionDetails, NewChallenge, Verdict};
+    use crate::persistence::{build_item, Persistence};
+    use crate::theme::Theme;
+
+    fn rng(seed: u64) -> StdRng {
+        StdRng::seed_from_u64(seed)
+    }
+
+    #[test]
+    fn test_seeded_uuid() {
+        assert_eq!(create_uuid(Some(&mut rng(42))), create_uuid(Some(&mut rng(42))));
+        assert_ne!(create_uuid(Some(&mut rng(42))), create_uuid(Some(&mut rng(43))));
+        assert_eq!(create_uuid(Some(&mut rng(42))).len(), 36);
+    }
+
+    #[test]
+    fn test_seeded_captcha() {
+        let a = create_captcha(Difficulty::Medium, Some(&mut rng(7)), None).unwrap();
+        let b = create_captcha(Difficulty::Medium, Some(&mut rng(7)), None).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_new_response() {
+        let d = CaptchaNewDetails {
+            uuid: String::from("x"),
+            challenge: NewChallenge::Image { solution: String::from("secret"), png: vec![1, 2, 3] },
+            tries_left: Some(3),
+            expires: None,
+        };
+        assert_eq!(d.solution(), Some("secret"));
+        assert_eq!(d.png(), Some(&[1u8, 2, 3][..]));
+        assert!(serde_json::to_string(&d.response(true)).unwrap().contains("\"solution\":\"secret\""));
+        let public = serde_json::to_string(&d.response(false)).unwrap();
+        assert!(public.contains("\"png\":\"AQID\""));
+        assert!(!public.contains("solution"));
+    }
+
+    #[test]
+    fn test_solution_response() {
+        let d = CaptchaSolutionDetails { uuid: String::from("x"), verdict: Verdict::Incorrect { tries_left: 2 } };
+        assert!(!d.accepted());
+        assert_eq!(d.trials_left(), 2);
+        assert_eq!(d.response().result(), "incorrect");
+        let d = CaptchaSolutionDetails { uuid: String::from("x"), verdict: Verdict::Accepted };
+        assert!(d.response().accepted());
+    }
+
+    #[tokio::test]
+    async fn test_verdicts() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        let solution = c.solution().unwrap().to_string();
+        assert_eq!(captcha_solution(c.uuid(), "wrong".into(), None).await.unwrap().verdict(), Verdict::Incorrect { tries_left: 1 });
+        assert_eq!(captcha_solution(c.uuid(), solution.clone(), None).await.unwrap().verdict(), Verdict::Accepted);
+        assert_eq!(captcha_solution(c.uuid(), solution, None).await.unwrap().verdict(), Verdict::AlreadyUsed);
+
+        let c = captcha_new("easy".into(), "1".into(), "60".into(), "test".into(), None).await.unwrap();
+        assert_eq!(captcha_solution(c.uuid(), "wrong".into(), None).await.unwrap().verdict(), Verdict::Incorrect { tries_left: 0 });
+        assert_eq!(captcha_solution(c.uuid(), "wrong".into(), None).await.unwrap().verdict(), Verdict::Exhausted);
+
+        let id = create_uuid(None);
+        assert_eq!(captcha_solution(id, "x".into(), None).await.unwrap().verdict(), Verdict::NotFound);
+
+        // The nonce of a proof-of-work challenge with an invalid number of bits is no solution.
+        let id = create_uuid(None);
+        let item = build_item().uuid(id.clone()).solution("nonce").tries_left(1).ttl(60).challenge("pow-0").client("test").item().unwrap();
+        Persistence::set(item).await.unwrap();
+        assert!(captcha_solution(id, "nonce".into(), None).await.is_err());
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        // Only one of concurrent refreshes of the same CAPTCHA succeeds.
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        let (a, b, d) = tokio::join!(captcha_refresh(c.uuid(), None), captcha_refresh(c.uuid(), None), captcha_refresh(c.uuid(), None));
+        let mut refreshed: Vec<_> = vec![a, b, d].into_iter().filter_map(|r| r.ok()).collect();
+        assert_eq!(refreshed.len(), 1);
+        let r = refreshed.remove(0);
+        assert_eq!(r.tries_left(), Some(2));
+        assert_eq!(captcha_solution(c.uuid(), "x".into(), None).await.unwrap().verdict(), Verdict::NotFound);
+
+        // The refreshes are counted along the chain of CAPTCHAs.
+        let r = captcha_refresh(r.uuid(), None).await.unwrap();
+        let r = captcha_refresh(r.uuid(), None).await.unwrap();
+        assert!(matches!(captcha_refresh(r.uuid(), None).await, Err(CaptchaError::TooManyRefreshes)));
+        let solution = r.solution().unwrap().to_string();
+        assert_eq!(captcha_solution(r.uuid(), solution, None).await.unwrap().verdict(), Verdict::Accepted);
+
+        // Only the client which has created a CAPTCHA can refresh it.
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        assert!(matches!(captcha_refresh(c.uuid(), Some("other".into())).await, Err(CaptchaError::Unauthorized)));
+        assert!(captcha_refresh(c.uuid(), Some("test".into())).await.is_ok());
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_redeem() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let c = captcha_new_token("easy".into(), "2".into(), "60".into(), "test".into()).await.unwrap();
+        assert!(!captcha_redeem(c.uuid(), Caller::Trusted).await.unwrap().redeemed());
+        let solution = c.solution().unwrap().to_string();
+        assert_eq!(captcha_solution(c.uuid(), solution, None).await.unwrap().verdict(), Verdict::Accepted);
+        assert!(captcha_redeem(c.uuid(), Caller::Trusted).await.unwrap().redeemed());
+        assert!(!captcha_redeem(c.uuid(), Caller::Trusted).await.unwrap().redeemed());
+
+        // The solution of a CAPTCHA created by captcha_new has been revealed to the client.
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        let solution = c.solution().unwrap().to_string();
+        assert_eq!(captcha_solution(c.uuid(), solution, None).await.unwrap().verdict(), Verdict::Accepted);
+        assert!(!captcha_redeem(c.uuid(), Caller::Trusted).await.unwrap().redeemed());
+
+        // Refreshed CAPTCHAs keep their kind.
+        let c = captcha_new_token("easy".into(), "2".into(), "60".into(), "test".into()).await.unwrap();
+        let r = captcha_refresh(c.uuid(), None).await.unwrap();
+        let solution = r.solution().unwrap().to_string();
+        assert_eq!(captcha_solution(r.uuid(), solution, None).await.unwrap().verdict(), Verdict::Accepted);
+        assert!(captcha_redeem(r.uuid(), Caller::Trusted).await.unwrap().redeemed());
+        assert!(!captcha_redeem(create_uuid(None), Caller::Trusted).await.unwrap().redeemed());
+        assert!(matches!(captcha_redeem("x".into(), Caller::Trusted).await, Err(CaptchaError::InvalidParameters)));
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_audio() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        assert!(captcha_audio(c.uuid()).await.unwrap().starts_with(b"RIFF"));
+        let p = captcha_new("pow".into(), "1".into(), "60".into(), "test".into(), None).await.unwrap();
+        assert!(matches!(captcha_audio(p.uuid()).await, Err(CaptchaError::InvalidParameters)));
+        assert!(matches!(captcha_audio(create_uuid(None)).await, Err(CaptchaError::NotFound)));
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[test]
+    fn test_theme() {
+        // The size is stored in the IHDR chunk after the signature of the PNG.
+        let size = |png: &[u8]| (u32::from_be_bytes([png[16], png[17], png[18], png[19]]), u32::from_be_bytes([png[20], png[21], png[22], png[23]]));
+
+        let (_, png) = create_captcha(Difficulty::Easy, Some(&mut rng(1)), None).unwrap();
+        assert_eq!(size(&png), (220, 120));
+
+        let theme = Theme { width: Some(300), height: Some(100), background: Some("#202124".into()), ..Theme::default() };
+        let (_, png) = create_captcha(Difficulty::Easy, Some(&mut rng(1)), Some(&theme)).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(size(&png), (300, 100));
+        let (_, png) = create_captcha(Difficulty::Easy, None, Some(&theme)).unwrap();
+        assert_eq!(size(&png), (300, 100));
+    }
+
+    #[tokio::test]
+    async fn test_animated() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let c = captcha_new("animated-easy".into(), "2".into(), "60".into(), "test".into(), Some(9)).await.unwrap();
+        assert_eq!(&c.gif().unwrap()[0..6], b"GIF89a");
+        assert!(c.png().is_none());
+        assert!(serde_json::to_string(&c.response(false)).unwrap().contains("\"gif\":\"R0lGODlh"));
+        let solution = c.solution().unwrap().to_string();
+        assert_eq!(solution.len(), 4);
+        assert_eq!(captcha_solution(c.uuid(), "wrong".into(), None).await.unwrap().verdict(), Verdict::Incorrect { tries_left: 1 });
+        assert_eq!(captcha_solution(c.uuid(), solution, None).await.unwrap().verdict(), Verdict::Accepted);
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_benchmark() {
+        let scores = captcha_benchmark(vec!["easy".into(), "hard".into()], 3, 1).await.unwrap();
+        assert_eq!(scores.len(), 10 + 11);
+        assert!(scores.iter().all(|s| s.count == 3));
+        assert_eq!((scores[0].difficulty.as_str(), scores[0].filters.as_str()), ("easy", "none"));
+        assert_eq!(scores, captcha_benchmark(vec!["easy".into(), "hard".into()], 3, 1).await.unwrap());
+        assert!(captcha_benchmark(vec!["extreme".into()], 3, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_generate() {
+        let a = captcha_generate(String::from("easy"), 5, Some(1)).await.unwrap();
+        let b = captcha_generate(String::from("easy"), 5, Some(1)).await.unwrap();
+        assert_eq!(a.len(), 5);
+        for (x, y) in a.captchas().iter().zip(b.captchas()) {
+            assert_eq!(x.uuid(), y.uuid());
+            assert_eq!(x.solution(), y.solution());
+            assert_eq!(x.png(), y.png());
+        }
+    }
 }
@@ -0,0 +1,298 @@
+//! Tower middleware which protects routes of axum or hyper services with a solved CAPTCHA. It is
+//! available with the feature `tower`.
+//!
+//! The middleware uses the same checks as the Rocket request guard `guard::SolvedCaptcha` and
+//! needs `REDIS_HOST` of the service:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/comment", post(comment))
+//!     .layer(CaptchaLayer::new().exempt("/health"));
+//! ```
+//!
+//! If the CAPTCHA is accepted, the request is passed on with a `SolvedCaptcha` in its extensions.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::{HeaderMap, Request, Response, StatusCode, Uri};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::guard::{GuardError, SolvedCaptcha, ID_HEADER, SOLUTION_HEADER, TOKEN_HEADER};
+
+/// What a request contains to prove that a CAPTCHA has been solved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    /// The id of a CAPTCHA and its solution.
+    Solution { id: String, solution: String },
+    /// A verification token, i.e. the id of a CAPTCHA which has been solved via the service.
+    Token(String),
+}
+
+type Extract = dyn Fn(&HeaderMap, &Uri) -> Option<Credentials> + Send + Sync;
+type Reject<B> = dyn Fn(GuardError) -> Response<B> + Send + Sync;
+
+/// Reads the credentials from the headers `X-Captcha-Id` and `X-Captcha-Solution` or from the
+/// header `X-Captcha-Token`.
+pub fn from_headers(headers: &HeaderMap, _: &Uri) -> Option<Credentials> {
+    let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(String::from);
+    match (get(ID_HEADER), get(SOLUTION_HEADER), get(TOKEN_HEADER)) {
+        (Some(id), Some(solution), _) => Some(Credentials::Solution { id, solution }),
+        (_, _, Some(token)) => Some(Credentials::Token(token)),
+        _ => None
+    }
+}
+
+/// Answers a rejected request with `403 Forbidden`, or `500 Internal Server Error` if the CAPTCHA
+/// could not be checked.
+pub fn forbidden<B: From<&'static str>>(e: GuardError) -> Response<B> {
+    let (status, body) = match e {
+        GuardError::Failed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal error"),
+        _ => (StatusCode::FORBIDDEN, "captcha required"),
+    };
+    let mut r = Response::new(B::from(body));
+    *r.status_mut() = status;
+    r
+}
+
+/// Layer which wraps a service with `CaptchaService`. `B` is the body type of the responses.
+pub struct CaptchaLayer<B> {
+    extract: Arc<Extract>,
+    reject: Arc<Reject<B>>,
+    exempt: Arc<Vec<String>>,
+}
+
+impl<B> Clone for CaptchaLayer<B> {
+    fn clone(&self) -> Self {
+        CaptchaLayer {
+            extract: self.extract.clone(),
+            reject: self.reject.clone(),
+            exempt: self.exempt.clone(),
+        }
+    }
+}
+
+impl<B: From<&'static str> + 'static> CaptchaLayer<B> {
+    /// Creates a layer which reads the credentials from the headers and rejects requests with
+    /// `forbidden`.
+    pub fn new() -> CaptchaLayer<B> {
+        CaptchaLayer::with_rejection(forbidden)
+    }
+}
+
+impl<B: From<&'static str> + 'static> Default for CaptchaLayer<B> {
+    fn default() -> Self {
+        CaptchaLayer::new()
+    }
+}
+
+impl<B> CaptchaLayer<B> {
+    /// Creates a layer which answers rejected requests with the response returned by `reject`.
+    pub fn with_rejection<F>(reject: F) -> CaptchaLayer<B>
+        where F: Fn(GuardError) -> Response<B> + Send + Sync + 'static {
+        CaptchaLayer {
+            extract: Arc::new(from_headers),
+            reject: Arc::new(reject),
+            exempt: Arc::new(vec![]),
+        }
+    }
+
+    /// Sets the function which reads the credentials from a request.
+    pub fn extract<F>(mut self, extract: F) -> CaptchaLayer<B>
+        where F: Fn(&HeaderMap, &Uri) -> Option<Credentials> + Send + Sync + 'static {
+        self.extract = Arc::new(extract);
+        self
+    }
+
+    /// Passes requests whose path is `prefix` or below `prefix` without a check. The prefix is
+    /// matched by whole segments: `/health` exempts `/health/db` but not `/healthz`.
+    pub fn exempt(mut self, prefix: &str) -> CaptchaLayer<B> {
+        Arc::make_mut(&mut self.exempt).push(prefix.to_string());
+        self
+    }
+}
+
+/// Returns whether `path` is `prefix` or a path below `prefix`.
+fn exempted(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false
+    }
+}
+
+impl<S, B> Layer<S> for CaptchaLayer<B> {
+    type Service = CaptchaService<S, B>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CaptchaService { inner, layer: self.clone() }
+    }
+}
+
+/// Service which passes a request to the inner service only if it contains a solved CAPTCHA.
+pub struct CaptchaService<S, B> {
+    inner: S,
+    layer: CaptchaLayer<B>,
+}
+
+impl<S: Clone, B> Clone for CaptchaService<S, B> {
+    fn clone(&self) -> Self {
+        CaptchaService { inner: self.inner.clone(), layer: self.layer.clone() }
+    }
+}
+
+impl<S, ReqB, B> Service<Request<ReqB>> for CaptchaService<S, B>
+    where S: Service<Request<ReqB>, Response = Response<B>> + Clone + Send + 'static,
+          S::Future: Send,
+          ReqB: Send + 'static,
+          B: 'static {
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqB>) -> Self::Future {
+        // The inner service which has been polled is used for this request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let path = req.uri().path();
+        if self.layer.exempt.iter().any(|p| exempted(path, p)) {
+            return Box::pin(inner.call(req));
+        }
+
+        let credentials = (self.layer.extract)(req.headers(), req.uri());
+        let reject = self.layer.reject.clone();
+        Box::pin(async move {
+            let r = match credentials {
+                Some(Credentials::Solution { id, solution }) => SolvedCaptcha::verify(&id, Some(&solution)).await,
+                Some(Credentials::Token(token)) => SolvedCaptcha::verify(&token, None).await,
+                None => Err(GuardError::Missing)
+            };
+            match r {
+                Ok(c) => {
+                    req.extensions_mut().insert(c);
+                    inner.call(req).await
+                },
+                Err(e) => {
+                    if let GuardError::Failed(ref e) = e {
+                        error!("Could not verify CAPTCHA [{:?}].", e);
+                    }
+                    Ok(reject(e))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::env;
+    use std::future::{ready, Ready};
+    use std::task::{Context, Poll};
+
+    use http::{HeaderMap, Request, Response, StatusCode, Uri};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use crate::guard::SolvedCaptcha;
+    use crate::methods::{captcha_new, captcha_new_token, captcha_solution};
+    use crate::middleware::{CaptchaLayer, Credentials, exempted, from_headers};
+
+    /// Answers with the id of the solved CAPTCHA.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            let id = req.extensions().get::<SolvedCaptcha>().map(|c| c.uuid()).unwrap_or_default();
+            ready(Ok(Response::new(id)))
+        }
+    }
+
+    fn request(uri: &str, headers: &[(&'static str, &str)]) -> Request<()> {
+        let mut r = Request::builder().uri(uri);
+        for (name, value) in headers {
+            r = r.header(*name, *value);
+        }
+        r.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let uri = Uri::from_static("/");
+        let mut h = HeaderMap::new();
+        assert_eq!(from_headers(&h, &uri), None);
+        h.insert("X-Captcha-Token", "t".parse().unwrap());
+        assert_eq!(from_headers(&h, &uri), Some(Credentials::Token(String::from("t"))));
+        h.insert("X-Captcha-Id", "i".parse().unwrap());
+        h.insert("X-Captcha-Solution", "s".parse().unwrap());
+        assert_eq!(from_headers(&h, &uri), Some(Credentials::Solution { id: String::from("i"), solution: String::from("s") }));
+    }
+
+    #[test]
+    fn test_exempted() {
+        assert!(exempted("/health", "/health"));
+        assert!(exempted("/health/db", "/health"));
+        assert!(!exempted("/healthz-admin", "/health"));
+        assert!(!exempted("/health-anything", "/health"));
+        assert!(exempted("/static/a.css", "/static/"));
+        assert!(exempted("/static", "/static/"));
+        assert!(!exempted("/statics", "/static/"));
+        assert!(exempted("/anything", "/"));
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let mut svc = CaptchaLayer::<String>::new().exempt("/health").layer(Echo);
+        assert_eq!(svc.call(request("/health", &[])).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(svc.call(request("/health/db", &[])).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(svc.call(request("/healthz-admin", &[])).await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(svc.call(request("/comment", &[])).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let c = captcha_new("easy".into(), "2".into(), "60".into(), "test".into(), None).await.unwrap();
+        let (id, solution) = (c.uuid(), c.solution().unwrap().to_string());
+        let r = svc.call(request("/comment", &[("X-Captcha-Id", &id), ("X-Captcha-Solution", &solution)])).await.unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert_eq!(r.body(), &id);
+
+        // Custom extraction from the query and a custom rejection.
+        let mut svc = CaptchaLayer::with_rejection(|_| {
+                let mut r = Response::new(String::from("solve the captcha"));
+                *r.status_mut() = StatusCode::UNAUTHORIZED;
+                r
+            })
+            .extract(|_, uri| uri.query().and_then(|q| q.strip_prefix("token=")).map(|t| Credentials::Token(t.to_string())))
+            .layer(Echo);
+        let t = captcha_new_token("easy".into(), "2".into(), "60".into(), "test".into()).await.unwrap();
+        assert!(captcha_solution(t.uuid(), t.solution().unwrap().to_string(), None).await.unwrap().accepted());
+        let r = svc.call(request(&format!("/comment?token={}", t.uuid()), &[])).await.unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        let r = svc.call(request(&format!("/comment?token={}", t.uuid()), &[])).await.unwrap();
+        assert_eq!(r.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(r.body(), "solve the captcha");
+
+        // The solution of the first CAPTCHA has been revealed, so its id is no token.
+        let r = svc.call(request(&format!("/comment?token={}", id), &[])).await.unwrap();
+        assert_eq!(r.status(), StatusCode::UNAUTHORIZED);
+
+        env::remove_var("REDIS_HOST");
+    }
+}
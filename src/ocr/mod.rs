@@ -0,0 +1,294 @@
+//! A baseline OCR solver to measure how well image CAPTCHAs resist automated solving.
+//!
+//! The solver removes grid lines and speckles, splits a CAPTCHA into characters at the columns
+//! without ink and compares each character with the glyphs of the font (template matching). It
+//! knows the font and the number of characters, hence it is a simple but well informed attacker.
+//! See `captcha_benchmark` in `methods` and the command `captcha-cli bench`.
+
+use captcha::RngCaptcha;
+use image::{GrayImage, Luma};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::filters::Profile;
+use crate::theme::CHARS;
+
+/// Size to which characters and glyphs are scaled before they are compared.
+const TEMPLATE_WIDTH: u32 = 16;
+const TEMPLATE_HEIGHT: u32 = 20;
+
+/// Columns with less ink are background, e.g. thin lines or noise.
+const MIN_INK: usize = 2;
+
+/// Segments which are narrower are noise.
+const MIN_WIDTH: u32 = 3;
+
+/// Rows and columns with a larger fraction of ink are lines of a grid.
+const MAX_LINE_INK: f32 = 0.8;
+
+pub struct Solver {
+    templates: Vec<(char, Vec<bool>)>,
+}
+
+impl Solver {
+    /// Creates a solver for the characters of a font from an image of each character.
+    pub fn new(glyphs: &[(char, GrayImage)]) -> Solver {
+        let templates = glyphs.iter()
+            .filter_map(|(c, g)| template(&clean(g), 0, g.width()).map(|t| (*c, t)))
+            .collect();
+        Solver { templates }
+    }
+
+    /// Returns the `n` characters of a CAPTCHA or `None` if the image has no characters.
+    pub fn solve(&self, png: &[u8], n: usize) -> Option<String> {
+        let img = clean(&image::load_from_memory(png).ok()?.to_luma8());
+        let segments = segments(&img, n);
+        if segments.is_empty() {
+            return None;
+        }
+        segments.into_iter()
+            .map(|(x0, x1)| self.classify(&img, x0, x1))
+            .collect()
+    }
+
+    /// Returns the character whose glyph differs in the fewest pixels from the ink in the
+    /// columns `x0..x1`.
+    fn classify(&self, img: &GrayImage, x0: u32, x1: u32) -> Option<char> {
+        let t = template(img, x0, x1)?;
+        self.templates.iter()
+            .min_by_key(|(_, g)| g.iter().zip(&t).filter(|(a, b)| a != b).count())
+            .map(|(c, _)| *c)
+    }
+}
+
+impl Default for Solver {
+    /// A solver for the default font of the `captcha` crate.
+    fn default() -> Solver {
+        let glyphs: Vec<(char, GrayImage)> = CHARS.chars().filter_map(|c| Some((c, glyph(c)?))).collect();
+        Solver::new(&glyphs)
+    }
+}
+
+/// Draws a character with the default font of the `captcha` crate.
+fn glyph(c: char) -> Option<GrayImage> {
+    let png = RngCaptcha::from_rng(StdRng::seed_from_u64(0))
+        .set_chars(&[c])
+        .add_chars(1)
+        .view(100, 100)
+        .as_png()?;
+    Some(image::load_from_memory(&png).ok()?.to_luma8())
+}
+
+/// Removes the lines of a grid and replaces each pixel with the majority of its neighbourhood,
+/// which removes speckles.
+fn clean(img: &GrayImage) -> GrayImage {
+    let (w, h) = img.dimensions();
+    let mut lines = img.clone();
+    for y in 0..h {
+        if (0..w).filter(|&x| ink(img, x, y)).count() as f32 > MAX_LINE_INK * w as f32 {
+            for x in 0..w {
+                lines.put_pixel(x, y, Luma([255]));
+            }
+        }
+    }
+    for x in 0..w {
+        if (0..h).filter(|&y| ink(img, x, y)).count() as f32 > MAX_LINE_INK * h as f32 {
+            for y in 0..h {
+                lines.put_pixel(x, y, Luma([255]));
+            }
+        }
+    }
+    // The second pass removes the speckles which are left by the first one.
+    majority(&majority(&lines))
+}
+
+fn majority(img: &GrayImage) -> GrayImage {
+    let (w, h) = img.dimensions();
+    GrayImage::from_fn(w, h, |x, y| {
+        let n = (x.saturating_sub(1)..(x + 2).min(w))
+            .flat_map(|nx| (y.saturating_sub(1)..(y + 2).min(h)).map(move |ny| (nx, ny)))
+            .filter(|&(nx, ny)| ink(img, nx, ny))
+            .count();
+        if n >= 5 { Luma([0]) } else { Luma([255]) }
+    })
+}
+
+fn ink(img: &GrayImage, x: u32, y: u32) -> bool {
+    img.get_pixel(x, y).0[0] < 128
+}
+
+/// Splits the image into `n` ranges of columns, one for each character.
+fn segments(img: &GrayImage, n: usize) -> Vec<(u32, u32)> {
+    let (w, h) = img.dimensions();
+    let mut r: Vec<(u32, u32)> = vec![];
+    let mut start = None;
+    for x in 0..=w {
+        let has_ink = x < w && (0..h).filter(|&y| ink(img, x, y)).count() >= MIN_INK;
+        match (has_ink, start) {
+            (true, None) => start = Some(x),
+            (false, Some(s)) => {
+                if x - s >= MIN_WIDTH {
+                    r.push((s, x));
+                }
+                start = None;
+            },
+            _ => {}
+        }
+    }
+
+    // Touching characters are split and parts of broken characters are merged until there is
+    // one segment per character.
+    while r.len() > n && r.len() > 1 {
+        let i = (0..r.len() - 1).min_by_key(|&i| r[i + 1].0 - r[i].1).unwrap_or(0);
+        r[i].1 = r[i + 1].1;
+        r.remove(i + 1);
+    }
+    while r.len() < n && !r.is_empty() {
+        let i = (0..r.len()).max_by_key(|&i| r[i].1 - r[i].0).unwrap_or(0);
+        let (x0, x1) = r[i];
+        if x1 - x0 < 2 {
+            break;
+        }
+        let m = (x0 + x1) / 2;
+        r[i] = (x0, m);
+        r.insert(i + 1, (m, x1));
+    }
+    r
+}
+
+/// Crops the ink in the columns `x0..x1` and scales it to the size of a template.
+fn template(img: &GrayImage, x0: u32, x1: u32) -> Option<Vec<bool>> {
+    let rows: Vec<u32> = (0..img.height()).filter(|&y| (x0..x1).any(|x| ink(img, x, y))).collect();
+    let (y0, y1) = (*rows.first()?, *rows.last()? + 1);
+    let cols: Vec<u32> = (x0..x1).filter(|&x| (y0..y1).any(|y| ink(img, x, y))).collect();
+    let (x0, x1) = (*cols.first()?, *cols.last()? + 1);
+    let (w, h) = (x1 - x0, y1 - y0);
+    Some((0..TEMPLATE_HEIGHT)
+        .flat_map(|ty| (0..TEMPLATE_WIDTH).map(move |tx| (tx, ty)))
+        .map(|(tx, ty)| ink(img, x0 + tx * w / TEMPLATE_WIDTH, y0 + ty * h / TEMPLATE_HEIGHT))
+        .collect())
+}
+
+/// Returns the sets of filters of a profile which are evaluated: no filters, each filter of the
+/// profile alone and all filters.
+pub fn filter_sets(profile: &Profile) -> Vec<(String, Profile)> {
+    let none = Profile::default();
+    let mut r = vec![(String::from("none"), none.clone())];
+    let filters = [
+        ("jitter", Profile { jitter: profile.jitter.clone(), ..none.clone() }),
+        ("speckle", Profile { speckle: profile.speckle.clone(), ..none.clone() }),
+        ("grid", Profile { grid: profile.grid.clone(), ..none.clone() }),
+        ("wave", Profile { wave: profile.wave.clone(), ..none.clone() }),
+        ("warp", Profile { warp: profile.warp.clone(), ..none.clone() }),
+        ("dots", Profile { dots: profile.dots.clone(), ..none.clone() }),
+        ("occlusion", Profile { occlusion: profile.occlusion.clone(), ..none.clone() }),
+        ("clutter", Profile { clutter: profile.clutter.clone(), ..none.clone() }),
+        ("noise", Profile { noise: profile.noise.clone(), ..none.clone() }),
+    ];
+    // A set with a filter which the profile does not have equals the set without filters.
+    r.extend(filters.iter()
+        .filter(|(_, p)| *p != none)
+        .map(|(n, p)| (n.to_string(), p.clone())));
+    r.push((String::from("all"), profile.clone()));
+    r
+}
+
+/// How many CAPTCHAs of a difficulty with a set of filters the solver solved.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Score {
+    pub difficulty: String,
+    pub filters: String,
+    pub count: usize,
+    pub solved: usize,
+    /// Number of characters of all CAPTCHAs.
+    pub chars: usize,
+    /// Number of characters which were recognized at the right position.
+    pub chars_recognized: usize,
+}
+
+impl Score {
+    pub fn new(difficulty: &str, filters: &str) -> Score {
+        Score {
+            difficulty: difficulty.to_string(),
+            filters: filters.to_string(),
+            count: 0,
+            solved: 0,
+            chars: 0,
+            chars_recognized: 0,
+        }
+    }
+
+    /// Adds the result of the solver for a CAPTCHA.
+    pub fn add(&mut self, solution: &str, guess: Option<&str>) {
+        let guess = guess.unwrap_or("");
+        self.count += 1;
+        if guess == solution {
+            self.solved += 1;
+        }
+        self.chars += solution.chars().count();
+        self.chars_recognized += solution.chars().zip(guess.chars()).filter(|(a, b)| a == b).count();
+    }
+
+    /// Fraction of the CAPTCHAs which were solved.
+    pub fn solve_rate(&self) -> f64 {
+        self.solved as f64 / self.count.max(1) as f64
+    }
+
+    /// Fraction of the characters which were recognized.
+    pub fn char_rate(&self) -> f64 {
+        self.chars_recognized as f64 / self.chars.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use crate::filters::Profile;
+    use crate::ocr::{filter_sets, glyph, Score, Solver};
+    use crate::theme::encode_png;
+
+    /// Draws the glyphs of the default font side by side.
+    fn image(text: &str) -> Vec<u8> {
+        let mut img = GrayImage::from_pixel(300, 120, Luma([255]));
+        let mut x0 = 20;
+        for c in text.chars() {
+            let glyph = glyph(c).unwrap();
+            let cols: Vec<u32> = (0..glyph.width()).filter(|&x| (0..glyph.height()).any(|y| glyph.get_pixel(x, y).0[0] < 128)).collect();
+            let (g0, g1) = (cols[0], cols[cols.len() - 1] + 1);
+            for (x, y, p) in glyph.enumerate_pixels() {
+                if p.0[0] < 128 {
+                    img.put_pixel(x0 + x - g0, y + 10, Luma([0]));
+                }
+            }
+            x0 += g1 - g0 + 4;
+        }
+        encode_png(image::DynamicImage::ImageLuma8(img).to_rgb8()).unwrap()
+    }
+
+    #[test]
+    fn test_solve() {
+        let solver = Solver::default();
+        assert_eq!(solver.solve(&image("4aBk"), 4), Some(String::from("4aBk")));
+        assert_eq!(solver.solve(&image("Xy7mP"), 5), Some(String::from("Xy7mP")));
+        assert_eq!(solver.solve(&image(""), 4), None);
+    }
+
+    #[test]
+    fn test_score() {
+        let mut s = Score::new("easy", "all");
+        s.add("abcd", Some("abcd"));
+        s.add("abcd", Some("abxd"));
+        s.add("abcd", None);
+        assert_eq!((s.count, s.solved, s.chars, s.chars_recognized), (3, 1, 12, 7));
+        assert!((s.solve_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_filter_sets() {
+        let names = |p: &Profile| filter_sets(p).into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        assert_eq!(names(&Profile::builtin("hard")), vec!["none", "jitter", "speckle", "grid", "wave", "warp", "dots", "occlusion", "clutter", "noise", "all"]);
+        assert_eq!(names(&Profile::builtin("easy")), vec!["none", "jitter", "speckle", "grid", "wave", "warp", "dots", "occlusion", "noise", "all"]);
+        assert_eq!(filter_sets(&Profile::builtin("hard"))[0].1, Profile::default());
+    }
+}
@@ -5,7 +5,15 @@ pub struct Item {
     uuid: String,
     solution: String,
     tries_left: usize,
-    expires: i64
+    expires: i64,
+    #[serde(default)]
+    challenge: String,
+    #[serde(default)]
+    client: String,
+    #[serde(default)]
+    refreshes: usize,
+    #[serde(default)]
+    token: bool
 }
 
 impl Item {
@@ -25,6 +33,28 @@ impl Item {
         self.expires
     }
 
+    /// The type of the challenge, e.g. `easy` or `pow-20`. Empty for items created by older
+    /// versions of the service, which are always image CAPTCHAs.
+    pub fn challenge(&self) -> String {
+        self.challenge.clone()
+    }
+
+    /// The id of the client which has created the item.
+    pub fn client(&self) -> String {
+        self.client.clone()
+    }
+
+    /// Number of times the CAPTCHA has been replaced by a new one.
+    pub fn refreshes(&self) -> usize {
+        self.refreshes
+    }
+
+    /// Whether the id of the CAPTCHA is a verification token, i.e. the CAPTCHA has been created
+    /// without revealing its solution to the client.
+    pub fn token(&self) -> bool {
+        self.token
+    }
+
     pub fn dec_tries_left(&self) -> Item {
         let r = self.clone();
         Item { tries_left: self.tries_left - 1, .. r }
@@ -36,6 +66,10 @@ pub struct ItemBuilder {
     solution: Option<String>,
     tries_left: Option<usize>,
     expires: Option<Tm>,
+    challenge: Option<String>,
+    client: Option<String>,
+    refreshes: usize,
+    token: bool,
 }
 
 pub fn build_item() -> ItemBuilder {
@@ -43,7 +77,11 @@ pub fn build_item() -> ItemBuilder {
         uuid: None,
         solution: None,
         tries_left: None,
-        expires: None
+        expires: None,
+        challenge: None,
+        client: None,
+        refreshes: 0,
+        token: false
     }
 }
 
@@ -73,12 +111,36 @@ impl ItemBuilder {
         self
     }
 
+    pub fn challenge<T: ToString>(&mut self, challenge: T) -> &mut Self {
+        self.challenge = Some(challenge.to_string());
+        self
+    }
+
+    pub fn client<T: ToString>(&mut self, client: T) -> &mut Self {
+        self.client = Some(client.to_string());
+        self
+    }
+
+    pub fn refreshes(&mut self, refreshes: usize) -> &mut Self {
+        self.refreshes = refreshes;
+        self
+    }
+
+    pub fn token(&mut self, token: bool) -> &mut Self {
+        self.token = token;
+        self
+    }
+
     pub fn item(&self) -> Result<Item, ()> {
         Ok(Item {
             uuid      : self.uuid.clone().ok_or(())?.clone(),
             solution  : self.solution.clone().ok_or(())?,
             tries_left: self.tries_left.ok_or(())?,
-            expires   : self.expires.ok_or(())?.to_timespec().sec
+            expires   : self.expires.ok_or(())?.to_timespec().sec,
+            challenge : self.challenge.clone().unwrap_or_default(),
+            client    : self.client.clone().unwrap_or_default(),
+            refreshes : self.refreshes,
+            token     : self.token
         })
     }
 }
@@ -1,36 +1,277 @@
 mod error;
 mod item;
+mod tombstone;
 
 use std::env;
-use time;
-use redis::{Client, Commands, RedisResult, Connection};
-use serde_json;
+use std::collections::HashMap;
+use redis::{AsyncCommands, Client, RedisResult, Script};
+use redis::aio::MultiplexedConnection;
 
 // exports
 pub use self::error::Error;
 pub use self::item::{build_item, Item};
+pub use self::tombstone::{Tombstone, TombstoneState};
 
 pub type QueryResult = Result<Item, Error>;
 
 pub struct Persistence { }
 
 impl Persistence {
-    pub fn set(i: Item) -> Result<(), Error> {
-        connect()?
-            .set_ex::<String, String, String>(key(i.uuid()), serde_json::to_string(&i).map_err(|_| Error::Json)?, ttl(&i))
+    pub async fn set(i: Item) -> Result<(), Error> {
+        Persistence::set_many(&[i]).await
+    }
+
+    /// Stores several items with a single request.
+    pub async fn set_many(items: &[Item]) -> Result<(), Error> {
+        let mut p = redis::pipe();
+        p.atomic();
+        for i in items {
+            // The item is indexed by its client so that all items of a client can be found. The
+            // tombstone outlives the item so that an expired item can be told apart from an item
+            // that never existed.
+            let t = ttl(i);
+            let tombstone = Tombstone::new(TombstoneState::Expired, i.expires(), i.client(), i.token());
+            p.set_ex(key(i.uuid()), serde_json::to_string(i).map_err(|_| Error::Json)?, t).ignore()
+                .set_ex(client_key(&i.client(), &i.uuid()), "", t).ignore()
+                .set_ex(tombstone_key(&i.uuid()), serde_json::to_string(&tombstone).map_err(|_| Error::Json)?, t + tombstone_ttl()).ignore();
+        }
+        p.query_async::<_, ()>(&mut connect().await?).await
+            .map_err(|_| Error::Connection)
+    }
+
+    /// Removes an item that has been solved and its index entry and records this in its
+    /// tombstone.
+    pub async fn solved(i: &Item) -> Result<(), Error> {
+        let tombstone = Tombstone::new(TombstoneState::Solved, i.expires(), i.client(), i.token());
+        redis::pipe()
+            .atomic()
+            .del(vec![key(i.uuid()), client_key(&i.client(), &i.uuid())]).ignore()
+            .set_ex(tombstone_key(&i.uuid()), serde_json::to_string(&tombstone).map_err(|_| Error::Json)?, tombstone_ttl()).ignore()
+            .query_async::<_, ()>(&mut connect().await?).await
             .map_err(|_| Error::Connection)
-            .map(|_| ())
     }
 
-    pub fn get<T: ToString>(uuid: T) -> QueryResult {
-        Ok(parse_result(connect()?.get(key(uuid.to_string())))?)
+    /// Returns the tombstone of an item.
+    pub async fn tombstone<T: ToString>(uuid: T) -> Result<Tombstone, Error> {
+        connect().await?
+            .get::<_, Option<String>>(tombstone_key(&uuid.to_string())).await
+            .map_err(|_| Error::Connection)?
+            .ok_or(Error::NotFound)
+            .and_then(|s| serde_json::from_str(&s).map_err(|_| Error::Json))
+    }
+
+    /// Marks the token of a solved item as used. Returns `false` if the token has been used
+    /// before. The mark is kept as long as the tombstone.
+    pub async fn redeem<T: ToString>(uuid: T) -> Result<bool, Error> {
+        redis::cmd("SET")
+            .arg(redeemed_key(&uuid.to_string()))
+            .arg("")
+            .arg("NX")
+            .arg("EX")
+            .arg(tombstone_ttl())
+            .query_async::<_, Option<String>>(&mut connect().await?).await
+            .map(|r| r.is_some())
+            .map_err(|_| Error::Connection)
+    }
+
+    pub async fn get<T: ToString>(uuid: T) -> QueryResult {
+        parse_result(connect().await?.get(key(uuid.to_string())).await)
+    }
+
+    /// Removes an item and its index entry. The tombstone is kept.
+    pub async fn del(i: &Item) {
+        if let Ok(mut c) = connect().await {
+            c.del::<_, ()>(vec![key(i.uuid()), client_key(&i.client(), &i.uuid())]).await.ok();
+        }
+    }
+
+    /// Removes an item and its index entry and returns the removed item. Returns
+    /// `Error::NotFound` if the item does not exist.
+    pub async fn remove<T: ToString>(uuid: T) -> QueryResult {
+        let i = Persistence::get(uuid).await?;
+        connect().await?
+            .del::<_, ()>(vec![key(i.uuid()), client_key(&i.client(), &i.uuid()), tombstone_key(&i.uuid())]).await
+            .map_err(|_| Error::Connection)
+            .map(|_| i)
     }
 
-    pub fn del<T: ToString>(uuid: T) {
-        connect().ok().and_then(|mut c| c.del::<String, Option<String>>(key(uuid.to_string())).ok());
+    /// Replaces the item `old` with the item `new` in a single step. Returns `Error::NotFound` if
+    /// `old` does not exist anymore or if its tries or refreshes have changed since it was read,
+    /// e.g. because it has been replaced by a concurrent request.
+    pub async fn replace(old: &Item, new: &Item) -> Result<(), Error> {
+        let t = ttl(new);
+        let tombstone = Tombstone::new(TombstoneState::Expired, new.expires(), new.client(), new.token());
+        let replaced = Script::new(REPLACE)
+            .key(key(old.uuid()))
+            .key(client_key(&old.client(), &old.uuid()))
+            .key(tombstone_key(&old.uuid()))
+            .key(key(new.uuid()))
+            .key(client_key(&new.client(), &new.uuid()))
+            .key(tombstone_key(&new.uuid()))
+            .arg(old.tries_left())
+            .arg(old.refreshes())
+            .arg(serde_json::to_string(new).map_err(|_| Error::Json)?)
+            .arg(serde_json::to_string(&tombstone).map_err(|_| Error::Json)?)
+            .arg(t)
+            .arg(t + tombstone_ttl())
+            .invoke_async::<_, u32>(&mut connect().await?).await
+            .map_err(|_| Error::Connection)?;
+        match replaced {
+            1 => Ok(()),
+            _ => Err(Error::NotFound)
+        }
+    }
+
+    /// Returns a page of the items of `client` or of all items if no client is given.
+    ///
+    /// The iteration starts with cursor 0. Besides the items, the cursor for the next page is
+    /// returned. If the returned cursor is 0, the iteration is complete. A page may contain more
+    /// or less than `count` items.
+    pub async fn list(client: Option<&str>, cursor: u64, count: usize) -> Result<(u64, Vec<Item>), Error> {
+        let mut con = connect().await?;
+        let (next, uuids) = match client {
+            Some(c) => scan_prefix(&mut con, &client_key(c, ""), cursor, count).await?,
+            None    => scan_prefix(&mut con, ITEM_PREFIX, cursor, count).await?
+        };
+        if uuids.is_empty() {
+            return Ok((next, vec![]));
+        }
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(uuids.into_iter().map(key).collect::<Vec<_>>())
+            .query_async(&mut con).await
+            .map_err(|_| Error::Connection)?;
+        // Items can expire between the scan and the get.
+        Ok((next, values.into_iter().filter_map(|v| parse_option(v).ok()).collect()))
+    }
+
+    /// Removes all items of `client`. Returns the number of removed items.
+    pub async fn purge(client: &str) -> Result<usize, Error> {
+        let mut con = connect().await?;
+        let prefix = client_key(client, "");
+        let mut n = 0;
+        let mut cursor = 0;
+        loop {
+            let (next, uuids) = scan_prefix(&mut con, &prefix, cursor, 1000).await?;
+            if !uuids.is_empty() {
+                n += con.del::<_, usize>(uuids.into_iter().map(key).collect::<Vec<_>>()).await
+                    .map_err(|_| Error::Connection)?;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        del_prefix(&mut con, &prefix).await?;
+        Ok(n)
+    }
+
+    /// Removes all items, index entries and tombstones. Returns the number of removed items.
+    pub async fn purge_all() -> Result<usize, Error> {
+        let mut con = connect().await?;
+        let n = del_prefix(&mut con, ITEM_PREFIX).await?;
+        del_prefix(&mut con, CLIENT_PREFIX).await?;
+        del_prefix(&mut con, TOMBSTONE_PREFIX).await?;
+        Ok(n)
+    }
+
+    /// Takes `n` tokens from the token bucket `bucket` which holds at most `capacity` tokens
+    /// and is refilled completely within `period` seconds.
+    ///
+    /// Returns 0 if the tokens were available. Otherwise, no token is taken and the number of
+    /// seconds after which the tokens will be available is returned.
+    pub async fn take_tokens<T: ToString>(bucket: T, capacity: u32, period: u32, n: u32) -> Result<u64, Error> {
+        Script::new(TOKEN_BUCKET)
+            .key(bucket_key(bucket.to_string()))
+            .arg(capacity)
+            .arg(period)
+            .arg(n)
+            .invoke_async::<_, u64>(&mut connect().await?).await
+            .map_err(|_| Error::Connection)
+    }
+
+    /// Increments the counter `field` of the counters `name` by `n`. All counters of `name` are
+    /// removed `window` seconds after the first counter has been incremented.
+    pub async fn count<T: ToString>(name: T, field: &str, n: u64, window: usize) -> Result<(), Error> {
+        Persistence::increment(name.to_string(), field, n, window, true).await
+    }
+
+    /// Increments the counter `field` of the counters `name` by one if `name` has counters.
+    pub async fn count_existing<T: ToString>(name: T, field: &str, window: usize) -> Result<(), Error> {
+        Persistence::increment(name.to_string(), field, 1, window, false).await
+    }
+
+    async fn increment(name: String, field: &str, n: u64, window: usize, create: bool) -> Result<(), Error> {
+        Script::new(COUNTER)
+            .key(counter_key(name))
+            .arg(field)
+            .arg(window)
+            .arg(n)
+            .arg(create as u8)
+            .invoke_async::<_, ()>(&mut connect().await?).await
+            .map_err(|_| Error::Connection)
+    }
+
+    /// Returns all counters of `name`.
+    pub async fn counters<T: ToString>(name: T) -> Result<HashMap<String, u64>, Error> {
+        connect().await?
+            .hgetall(counter_key(name.to_string())).await
+            .map_err(|_| Error::Connection)
     }
 }
 
+const COUNTER: &str = r"
+if ARGV[4] == '0' and redis.call('EXISTS', KEYS[1]) == 0 then
+    return
+end
+redis.call('HINCRBY', KEYS[1], ARGV[1], ARGV[3])
+if redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+";
+
+// The old item is compared with the state that has been read so that concurrent requests cannot
+// replace it twice or restore a try.
+const REPLACE: &str = r"
+local v = redis.call('GET', KEYS[1])
+if not v then
+    return 0
+end
+local old = cjson.decode(v)
+if old.tries_left ~= tonumber(ARGV[1]) or (old.refreshes or 0) ~= tonumber(ARGV[2]) then
+    return 0
+end
+redis.call('DEL', KEYS[1], KEYS[2], KEYS[3])
+redis.call('SET', KEYS[4], ARGV[3], 'EX', ARGV[5])
+redis.call('SET', KEYS[5], '', 'EX', ARGV[5])
+redis.call('SET', KEYS[6], ARGV[4], 'EX', ARGV[6])
+return 1
+";
+
+// The bucket is updated atomically on the Redis server so that several instances of the service
+// can share the same limits. The time of the Redis server is used to avoid problems with clocks
+// that are not in sync.
+const TOKEN_BUCKET: &str = r"
+local capacity = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local t = redis.call('TIME')
+local now = tonumber(t[1]) + tonumber(t[2]) / 1000000
+local n = tonumber(ARGV[3])
+local rate = capacity / period
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1]) or capacity
+local ts = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+local wait = 0
+if tokens >= n then
+    tokens = tokens - n
+else
+    wait = math.ceil((n - tokens) / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', tostring(now))
+redis.call('EXPIRE', KEYS[1], period)
+return wait
+";
+
 // -------------------------------------------------------------------------------------------------
 
 pub fn ttl(i: &Item) -> usize {
@@ -42,15 +283,91 @@ pub fn ttl(i: &Item) -> usize {
     }
 }
 
+const ITEM_PREFIX: &str = "X1:";
+const CLIENT_PREFIX: &str = "XC1:";
+const TOMBSTONE_PREFIX: &str = "XT1:";
+const REDEEMED_PREFIX: &str = "XR1:";
+
 fn key(k: String) -> String {
-    format!("X1:{}", k)
+    format!("{}{}", ITEM_PREFIX, k)
+}
+
+/// Number of seconds a tombstone is kept after the item has been removed. Configured via the
+/// environment variable `TOMBSTONE_TTL` (default: 300).
+fn tombstone_ttl() -> usize {
+    env::var("TOMBSTONE_TTL").ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .unwrap_or(300)
+}
+
+fn tombstone_key(uuid: &str) -> String {
+    format!("{}{}", TOMBSTONE_PREFIX, uuid)
+}
+
+fn redeemed_key(uuid: &str) -> String {
+    format!("{}{}", REDEEMED_PREFIX, uuid)
+}
+
+/// Returns the index key of an item. `:` and `%` in the client are percent-encoded, so that the
+/// keys of a client cannot start with the prefix of another client, e.g. `a` and `a:b`.
+fn client_key(client: &str, uuid: &str) -> String {
+    format!("{}{}:{}", CLIENT_PREFIX, client.replace('%', "%25").replace(':', "%3A"), uuid)
+}
+
+fn bucket_key(k: String) -> String {
+    format!("RL1:{}", k)
+}
+
+fn counter_key(k: String) -> String {
+    format!("ST1:{}", k)
+}
+
+fn escape_pattern(s: &str) -> String {
+    let mut r = String::new();
+    for c in s.chars() {
+        if "*?[]\\".contains(c) {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+/// Returns a page of the keys which start with `prefix`. The prefix is removed from the keys.
+async fn scan_prefix(con: &mut MultiplexedConnection, prefix: &str, cursor: u64, count: usize) -> Result<(u64, Vec<String>), Error> {
+    let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(format!("{}*", escape_pattern(prefix)))
+        .arg("COUNT")
+        .arg(count)
+        .query_async(con).await
+        .map_err(|_| Error::Connection)?;
+    Ok((next, keys.into_iter().map(|k| k[prefix.len()..].to_string()).collect()))
 }
 
-fn connect() -> Result<Connection, Error> {
-    Ok(Client::open(address()?.as_str())
+/// Removes all keys which start with `prefix`. Returns the number of removed keys.
+async fn del_prefix(con: &mut MultiplexedConnection, prefix: &str) -> Result<usize, Error> {
+    let mut n = 0;
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = scan_prefix(con, prefix, cursor, 1000).await?;
+        if !keys.is_empty() {
+            n += con.del::<_, usize>(keys.into_iter().map(|k| format!("{}{}", prefix, k)).collect::<Vec<_>>()).await
+                .map_err(|_| Error::Connection)?;
+        }
+        if next == 0 {
+            return Ok(n);
+        }
+        cursor = next;
+    }
+}
+
+async fn connect() -> Result<MultiplexedConnection, Error> {
+    Client::open(address()?.as_str())
         .map_err(|_| Error::Connection)?
-        .get_connection().map_err(|_| Error::Connection)?
-    )
+        .get_multiplexed_tokio_connection().await
+        .map_err(|_| Error::Connection)
 }
 
 fn address() -> Result<String, Error> {
@@ -75,12 +392,12 @@ fn parse_result(r: RedisResult<Option<String>>) -> QueryResult {
 #[cfg(test)]
 mod tests {
     use std::env;
-    use persistence::{Error, address, Persistence, parse_result, build_item};
+    use crate::persistence::{Error, address, client_key, connect, Persistence, parse_result, build_item, TombstoneState};
     use std::thread::sleep;
     use std::time::Duration;
-    use std::io::{self, ErrorKind};
-    use redis::RedisError;
-    use time;
+    use std::io;
+    use redis::{AsyncCommands, RedisError};
+    use uuid::Uuid;
 
     // For the following tests Redis must be running.
 
@@ -91,7 +408,7 @@ mod tests {
     #[test]
     fn test_parse_result() {
         assert_eq!(
-            parse_result(Err(RedisError::from(io::Error::new(ErrorKind::Other, "x")))).err().unwrap(), Error::Connection
+            parse_result(Err(RedisError::from(io::Error::other("x")))).err().unwrap(), Error::Connection
         );
 
         assert_eq!(parse_result(Ok(None)).err().unwrap(), Error::NotFound);
@@ -120,18 +437,18 @@ mod tests {
         env::remove_var("REDIS_HOST");
     }
 
-    #[test]
-    fn test_notfound() {
+    #[tokio::test]
+    async fn test_notfound() {
         env::set_var("REDIS_HOST", "localhost");
 
         // Search an element that does not exist.
-        assert_eq!(Persistence::get("xx").expect_err("a"), Error::NotFound);
+        assert_eq!(Persistence::get("xx").await.expect_err("a"), Error::NotFound);
 
         env::remove_var("REDIS_HOST");
     }
 
-    #[test]
-    fn test_expire() {
+    #[tokio::test]
+    async fn test_expire() {
         env::set_var("REDIS_HOST", "localhost");
 
         // Insert an element that will be expired after 1 second.
@@ -141,22 +458,22 @@ mod tests {
             .tries_left(3)
             .ttl(1)
             .item().expect("building item");
-        assert!(Persistence::set(i).is_ok());
+        assert!(Persistence::set(i).await.is_ok());
 
         // Check that the element exists.
-        assert_eq!(Persistence::get("uid1234").expect("b").solution(), "sol1234");
+        assert_eq!(Persistence::get("uid1234").await.expect("b").solution(), "sol1234");
 
         // Wait that the element is removed from Redis ...
         sleep(Duration::from_secs(2));
 
         // Check that item is removed.
-        assert_eq!(Persistence::get("uid1234").expect_err("c"), Error::NotFound);
+        assert_eq!(Persistence::get("uid1234").await.expect_err("c"), Error::NotFound);
 
         env::remove_var("REDIS_HOST");
     }
 
-    #[test]
-    fn test_persist() {
+    #[tokio::test]
+    async fn test_persist() {
         env::set_var("REDIS_HOST", "localhost");
 
         // Insert an element that will expire after 1 second.
@@ -167,16 +484,16 @@ mod tests {
             .ttl(1)
             .item()
             .expect("building item");
-        assert!(Persistence::set(i).is_ok());
+        assert!(Persistence::set(i).await.is_ok());
 
         // Check that the element exists.
-        assert_eq!(Persistence::get("uid_persist").expect("b").solution(), "solp");
+        assert_eq!(Persistence::get("uid_persist").await.expect("b").solution(), "solp");
 
         env::remove_var("REDIS_HOST");
     }
 
-    #[test]
-    fn test_delete() {
+    #[tokio::test]
+    async fn test_delete() {
         env::set_var("REDIS_HOST", "localhost");
 
         // Insert an element that will be expired after 10 second.
@@ -187,16 +504,170 @@ mod tests {
             .ttl(10)
             .item()
             .expect("building item");
-        assert!(Persistence::set(i).is_ok());
+        assert!(Persistence::set(i.clone()).await.is_ok());
 
         // Check that the element does exist.
-        assert_eq!(Persistence::get("uidr").unwrap().solution(), "solution123");
+        assert_eq!(Persistence::get("uidr").await.unwrap().solution(), "solution123");
 
         // Remove that item
-        Persistence::del("uidr");
+        Persistence::del(&i).await;
 
         // Check that item is removed.
-        assert_eq!(Persistence::get("uidr").expect_err("e"), Error::NotFound);
+        assert_eq!(Persistence::get("uidr").await.expect_err("e"), Error::NotFound);
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_set_many() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let items = ["uid_m1", "uid_m2"].iter().map(|id| build_item()
+            .uuid(id)
+            .solution(format!("sol_{}", id))
+            .tries_left(3)
+            .ttl(10)
+            .item()
+            .expect("building item")
+        ).collect::<Vec<_>>();
+        assert!(Persistence::set_many(&items).await.is_ok());
+
+        assert_eq!(Persistence::get("uid_m1").await.unwrap().solution(), "sol_uid_m1");
+        assert_eq!(Persistence::get("uid_m2").await.unwrap().solution(), "sol_uid_m2");
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_tombstone() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let i = build_item()
+            .uuid("uid_tomb")
+            .solution("sol")
+            .tries_left(3)
+            .ttl(10)
+            .item()
+            .expect("building item");
+        assert!(Persistence::set(i.clone()).await.is_ok());
+        assert_eq!(Persistence::tombstone("uid_tomb").await.unwrap().state(), TombstoneState::Expired);
+
+        assert!(Persistence::solved(&i).await.is_ok());
+        assert_eq!(Persistence::get("uid_tomb").await.expect_err("a"), Error::NotFound);
+        assert_eq!(Persistence::tombstone("uid_tomb").await.unwrap().state(), TombstoneState::Solved);
+        assert_eq!(Persistence::tombstone("uid_tomb").await.unwrap().expires(), i.expires());
+
+        assert_eq!(Persistence::tombstone("uid_none").await.expect_err("b"), Error::NotFound);
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_list_remove_purge() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let client = format!("test*{}", Uuid::new_v4());
+        for id in &["uid_l1", "uid_l2", "uid_l3"] {
+            let i = build_item()
+                .uuid(id)
+                .solution("sol")
+                .tries_left(3)
+                .ttl(10)
+                .client(&client)
+                .item()
+                .expect("building item");
+            assert!(Persistence::set(i).await.is_ok());
+        }
+
+        // Items of a client whose key starts with the key of the other client are not listed.
+        let other = build_item().uuid("uid_l4").solution("sol").tries_left(3).ttl(10).client(format!("{}:uid_l", client)).item().unwrap();
+        assert!(Persistence::set(other.clone()).await.is_ok());
+
+        // Collect all pages.
+        let mut uuids = vec![];
+        let mut cursor = 0;
+        loop {
+            let (next, items) = Persistence::list(Some(&client), cursor, 1).await.unwrap();
+            uuids.extend(items.into_iter().map(|i| i.uuid()));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        uuids.sort();
+        assert_eq!(uuids, vec!["uid_l1", "uid_l2", "uid_l3"]);
+
+        assert_eq!(Persistence::remove("uid_l1").await.unwrap().client(), client);
+        assert_eq!(Persistence::remove("uid_l1").await.expect_err("a"), Error::NotFound);
+
+        assert_eq!(Persistence::purge(&client).await, Ok(2));
+        assert_eq!(Persistence::get("uid_l2").await.expect_err("b"), Error::NotFound);
+        assert_eq!(Persistence::list(Some(&client), 0, 100).await.unwrap().1.len(), 0);
+
+        // Solved items are removed from the index.
+        assert_eq!(Persistence::list(Some(&other.client()), 0, 100).await.unwrap().1.len(), 1);
+        assert!(Persistence::solved(&other).await.is_ok());
+        let indexed: bool = connect().await.unwrap().exists(client_key(&other.client(), &other.uuid())).await.unwrap();
+        assert!(!indexed);
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_redeem() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let uuid = Uuid::new_v4().to_string();
+        assert_eq!(Persistence::redeem(&uuid).await, Ok(true));
+        assert_eq!(Persistence::redeem(&uuid).await, Ok(false));
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_take_token() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        // A bucket with two tokens which is refilled within 10 seconds.
+        let bucket = format!("test:{}", Uuid::new_v4());
+        assert_eq!(Persistence::take_tokens(&bucket, 2, 10, 1).await, Ok(0));
+        assert_eq!(Persistence::take_tokens(&bucket, 2, 10, 1).await, Ok(0));
+
+        // The bucket is empty. One token is refilled after 5 seconds.
+        let wait = Persistence::take_tokens(&bucket, 2, 10, 1).await.unwrap();
+        assert!(wait > 0 && wait <= 5);
+
+        // Several tokens are taken at once or not at all.
+        let bucket = format!("test:{}", Uuid::new_v4());
+        assert_eq!(Persistence::take_tokens(&bucket, 4, 10, 3).await, Ok(0));
+        let wait = Persistence::take_tokens(&bucket, 4, 10, 3).await.unwrap();
+        assert!(wait > 2 && wait <= 5);
+        assert_eq!(Persistence::take_tokens(&bucket, 4, 10, 1).await, Ok(0));
+
+        env::remove_var("REDIS_HOST");
+    }
+
+    #[tokio::test]
+    async fn test_count() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let name = format!("test:{}", Uuid::new_v4());
+        assert!(Persistence::counters(&name).await.unwrap().is_empty());
+
+        // Counters are only created by count.
+        Persistence::count_existing(&name, "a", 1).await.unwrap();
+        assert!(Persistence::counters(&name).await.unwrap().is_empty());
+
+        Persistence::count(&name, "a", 1, 1).await.unwrap();
+        Persistence::count(&name, "a", 3, 1).await.unwrap();
+        Persistence::count_existing(&name, "b", 1).await.unwrap();
+        let c = Persistence::counters(&name).await.unwrap();
+        assert_eq!(c.get("a"), Some(&4));
+        assert_eq!(c.get("b"), Some(&1));
+
+        // Wait until the window has expired.
+        sleep(Duration::from_secs(2));
+        assert!(Persistence::counters(&name).await.unwrap().is_empty());
 
         env::remove_var("REDIS_HOST");
     }
@@ -0,0 +1,44 @@
+/// The state of an item which has been removed.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TombstoneState {
+    Solved,
+    Expired,
+}
+
+/// A tombstone is kept for some time after an item has been removed so that the service can
+/// tell why the item does not exist anymore.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Tombstone {
+    state: TombstoneState,
+    expires: i64,
+    #[serde(default)]
+    client: String,
+    #[serde(default)]
+    token: bool,
+}
+
+impl Tombstone {
+    pub fn new(state: TombstoneState, expires: i64, client: String, token: bool) -> Tombstone {
+        Tombstone { state, expires, client, token }
+    }
+
+    pub fn state(&self) -> TombstoneState {
+        self.state
+    }
+
+    /// The expiration time of the removed item.
+    pub fn expires(&self) -> i64 {
+        self.expires
+    }
+
+    /// The client which has created the removed item.
+    pub fn client(&self) -> String {
+        self.client.clone()
+    }
+
+    /// Whether the id of the removed item is a verification token (see `Item::token`).
+    pub fn token(&self) -> bool {
+        self.token
+    }
+}
@@ -0,0 +1,96 @@
+use std::env;
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Number of leading zero bits of a solution if no difficulty is configured.
+pub const DEFAULT_BITS: u32 = 20;
+
+/// Maximum number of leading zero bits that can be requested.
+pub const MAX_BITS: u32 = 32;
+
+/// Creates a new random nonce for a proof-of-work challenge.
+pub fn create_nonce() -> String {
+    Uuid::new_v4().to_simple().to_string()
+}
+
+/// Returns the number of leading zero bits a solution of the client `clientid` must have.
+///
+/// The difficulty is read from the environment variable `POW_BITS_CLIENTS` which contains a
+/// comma separated list of `<clientid>=<bits>` pairs. If the client is not in the list,
+/// `POW_BITS` is used, and if this is not set either, `DEFAULT_BITS`. The result is at least 1
+/// because any solution has 0 leading zero bits.
+pub fn bits_for(clientid: &str) -> u32 {
+    env::var("POW_BITS_CLIENTS").ok()
+        .and_then(|s| client_bits(&s, clientid))
+        .or_else(|| env::var("POW_BITS").ok().and_then(|s| s.trim().parse::<u32>().ok()))
+        .unwrap_or(DEFAULT_BITS)
+        .clamp(1, MAX_BITS)
+}
+
+fn client_bits(clients: &str, clientid: &str) -> Option<u32> {
+    clients.split(',')
+        .filter_map(|entry| {
+            let mut kv = entry.splitn(2, '=');
+            Some((kv.next()?.trim(), kv.next()?.trim()))
+        })
+        .find(|&(c, _)| c == clientid)
+        .and_then(|(_, bits)| bits.parse::<u32>().ok())
+}
+
+/// Checks that the SHA-256 hash of `nonce` concatenated with `solution` has at least `bits`
+/// leading zero bits.
+pub fn verify(nonce: &str, bits: u32, solution: &str) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(nonce.as_bytes());
+    hasher.update(solution.as_bytes());
+    leading_zero_bits(&hasher.finalize()) >= bits
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut n = 0;
+    for b in hash {
+        n += b.leading_zeros();
+        if *b != 0 {
+            break;
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::pow::{bits_for, leading_zero_bits, client_bits, verify, MAX_BITS};
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0x80, 0x00]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x01]), 15);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+        assert_eq!(leading_zero_bits(&[0x0f, 0x00]), 4);
+    }
+
+    #[test]
+    fn test_client_bits() {
+        assert_eq!(client_bits("a=10, b = 12", "b"), Some(12));
+        assert_eq!(client_bits("a=10,b=12", "c"), None);
+        assert_eq!(client_bits("a=x", "a"), None);
+
+        // The clients are used by this test only.
+        env::set_var("POW_BITS_CLIENTS", "pow-test-zero=0,pow-test-max=99");
+        assert_eq!(bits_for("pow-test-zero"), 1);
+        assert_eq!(bits_for("pow-test-max"), MAX_BITS);
+        env::remove_var("POW_BITS_CLIENTS");
+    }
+
+    #[test]
+    fn test_verify() {
+        // Search a solution by brute force as a client would do.
+        let solution = (0..).map(|i: u32| i.to_string()).find(|s| verify("nonce", 8, s)).unwrap();
+        assert!(verify("nonce", 8, &solution));
+        assert!(verify("nonce", 0, "anything"));
+        assert!(!verify("nonce", 257, &solution));
+    }
+}
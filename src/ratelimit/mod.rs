@@ -0,0 +1,228 @@
+use std::env;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::persistence::Persistence;
+
+/// A token bucket which allows at most `capacity` requests within `period` seconds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Limit {
+    capacity: u32,
+    period: u32,
+}
+
+impl Limit {
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+}
+
+/// Parses a limit of the form `<requests>/<seconds>`, e.g. `100/60`.
+pub fn parse_limit(s: &str) -> Option<Limit> {
+    let mut parts = s.trim().splitn(2, '/');
+    let capacity = parts.next()?.trim().parse::<u32>().ok()?;
+    let period = parts.next()?.trim().parse::<u32>().ok()?;
+    if capacity == 0 || period == 0 {
+        return None;
+    }
+    Some(Limit { capacity, period })
+}
+
+/// The buckets of a route. Each client id and each IP has its own bucket.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Bucket {
+    Client,
+    Ip,
+}
+
+impl Bucket {
+    fn name(&self) -> &'static str {
+        match *self {
+            Bucket::Client => "client",
+            Bucket::Ip     => "ip",
+        }
+    }
+}
+
+/// Returns the limit of the buckets of `kind` for the given route.
+///
+/// The limit is read from the first of the following environment variables which is set:
+/// `RATE_LIMIT_<ROUTE>_<KIND>`, `RATE_LIMIT_<ROUTE>`, `RATE_LIMIT_<KIND>` and `RATE_LIMIT`, where
+/// `<KIND>` is `CLIENT` or `IP`. If none is set, the buckets are not limited.
+pub fn limit_for(route: &str, kind: Bucket) -> Option<Limit> {
+    let (r, k) = (route.to_uppercase(), kind.name().to_uppercase());
+    [format!("RATE_LIMIT_{}_{}", r, k), format!("RATE_LIMIT_{}", r), format!("RATE_LIMIT_{}", k), String::from("RATE_LIMIT")]
+        .iter()
+        .find_map(|v| env::var(v).ok())
+        .and_then(|s| {
+            let l = parse_limit(&s);
+            if l.is_none() {
+                warn!("Invalid rate limit [{}] for route [{}].", s, route);
+            }
+            l
+        })
+}
+
+/// Returns the list of trusted proxies from the environment variable `TRUSTED_PROXIES`, which
+/// contains a comma separated list of IP addresses.
+pub fn trusted_proxies() -> Vec<IpAddr> {
+    env::var("TRUSTED_PROXIES")
+        .map(|s| s.split(',').filter_map(|ip| IpAddr::from_str(ip.trim()).ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Determines the IP address of the client.
+///
+/// If the request was received from a trusted proxy, the `X-Forwarded-For` header is walked from
+/// right to left and the first address which is not a trusted proxy is returned. Otherwise, the
+/// remote address of the connection is returned as the header can be forged by the client.
+pub fn client_ip(remote: Option<IpAddr>, forwarded_for: Option<&str>, trusted: &[IpAddr]) -> Option<IpAddr> {
+    let mut ip = remote?;
+    if let Some(header) = forwarded_for {
+        for hop in header.rsplit(',') {
+            if !trusted.contains(&ip) {
+                break;
+            }
+            match IpAddr::from_str(hop.trim()) {
+                Ok(addr) => ip = addr,
+                Err(_)   => break
+            }
+        }
+    }
+    Some(ip)
+}
+
+/// The reason why a request is limited.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Limited {
+    /// The client should try again after the given number of seconds.
+    Wait(u64),
+    /// The request takes more tokens than a bucket holds and is never allowed.
+    Exceeded,
+}
+
+/// Checks whether a request to `route` from the client `clientid` with the IP `ip` is allowed.
+///
+/// Each request takes `tokens` tokens from the bucket of the client id and from the bucket of
+/// the IP, e.g. one token per CAPTCHA of a batch. A request which takes more tokens than one of
+/// the buckets holds is rejected without taking any token. If the store is not available, the
+/// request is allowed.
+pub async fn check(route: &str, clientid: Option<&str>, ip: Option<IpAddr>, tokens: u32) -> Result<(), Limited> {
+    let mut buckets = vec![];
+    if let Some(c) = clientid {
+        buckets.push((Bucket::Client, c.to_string()));
+    }
+    if let Some(i) = ip {
+        buckets.push((Bucket::Ip, i.to_string()));
+    }
+    let buckets: Vec<_> = buckets.into_iter()
+        .filter_map(|(kind, id)| limit_for(route, kind).map(|l| (kind, id, l)))
+        .collect();
+
+    let tokens = tokens.max(1);
+    if buckets.iter().any(|(_, _, l)| tokens > l.capacity()) {
+        return Err(Limited::Exceeded);
+    }
+
+    let mut wait = 0;
+    for (kind, id, limit) in buckets {
+        let b = format!("{}:{}:{}", kind.name(), route, id);
+        match Persistence::take_tokens(&b, limit.capacity(), limit.period(), tokens).await {
+            Ok(w)  => wait = wait.max(w),
+            Err(e) => error!("Could not check rate limit [{}] [{:?}].", b, e)
+        }
+    }
+
+    match wait {
+        0 => Ok(()),
+        w => Err(Limited::Wait(w))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::env;
+    use crate::ratelimit::{parse_limit, limit_for, client_ip, check, Bucket, Limited};
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        let l = parse_limit("100/60").unwrap();
+        assert_eq!(l.capacity(), 100);
+        assert_eq!(l.period(), 60);
+
+        assert_eq!(parse_limit(" 5 / 1 ").unwrap().capacity(), 5);
+        assert!(parse_limit("100").is_none());
+        assert!(parse_limit("0/60").is_none());
+        assert!(parse_limit("10/0").is_none());
+        assert!(parse_limit("a/b").is_none());
+    }
+
+    #[test]
+    fn test_limit_for() {
+        env::set_var("RATE_LIMIT", "100/60");
+        env::set_var("RATE_LIMIT_IP", "50/60");
+        env::set_var("RATE_LIMIT_TEST_NEW", "10/60");
+        env::set_var("RATE_LIMIT_TEST_NEW_CLIENT", "20/60");
+
+        assert_eq!(limit_for("test_new", Bucket::Client).unwrap().capacity(), 20);
+        assert_eq!(limit_for("test_new", Bucket::Ip).unwrap().capacity(), 10);
+        assert_eq!(limit_for("test_other", Bucket::Client).unwrap().capacity(), 100);
+        assert_eq!(limit_for("test_other", Bucket::Ip).unwrap().capacity(), 50);
+
+        env::remove_var("RATE_LIMIT");
+        env::remove_var("RATE_LIMIT_IP");
+        assert_eq!(limit_for("test_other", Bucket::Ip), None);
+        env::remove_var("RATE_LIMIT_TEST_NEW");
+        env::remove_var("RATE_LIMIT_TEST_NEW_CLIENT");
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies = vec![ip("10.0.0.1"), ip("10.0.0.2")];
+
+        // No header, no proxy.
+        assert_eq!(client_ip(Some(ip("1.2.3.4")), None, &proxies), Some(ip("1.2.3.4")));
+
+        // The header is ignored if the request is not received from a trusted proxy.
+        assert_eq!(client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &proxies), Some(ip("1.2.3.4")));
+
+        // The header is used if the request is received from a trusted proxy.
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), Some("5.6.7.8"), &proxies), Some(ip("5.6.7.8")));
+
+        // Chains of trusted proxies are followed but forged entries on the left are ignored.
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("9.9.9.9, 5.6.7.8, 10.0.0.2"), &proxies),
+            Some(ip("5.6.7.8"))
+        );
+
+        // Invalid entries stop the walk.
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), Some("garbage"), &proxies), Some(ip("10.0.0.1")));
+
+        assert_eq!(client_ip(None, Some("5.6.7.8"), &proxies), None);
+    }
+
+    #[rocket::async_test]
+    async fn test_check_exceeded() {
+        env::set_var("REDIS_HOST", "localhost");
+        env::set_var("RATE_LIMIT_TEST_BATCH_CLIENT", "10/60");
+        let clientid = format!("test-exceeded-{}", std::process::id());
+
+        // A batch larger than the bucket is rejected and does not take any token.
+        assert_eq!(check("test_batch", Some(&clientid), None, 11).await, Err(Limited::Exceeded));
+        assert_eq!(check("test_batch", Some(&clientid), None, 10).await, Ok(()));
+        assert!(matches!(check("test_batch", Some(&clientid), None, 1).await, Err(Limited::Wait(_))));
+
+        env::remove_var("RATE_LIMIT_TEST_BATCH_CLIENT");
+        env::remove_var("REDIS_HOST");
+    }
+}
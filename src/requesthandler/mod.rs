@@ -1,49 +1,317 @@
-use methods::{CaptchaError, captcha_new, captcha_solution, captcha_newget};
+use std::net::IpAddr;
 
-pub fn req_captcha_newget(difficulty: String, clientid: String) -> Result<String, CaptchaError> {
-    match captcha_newget(difficulty) {
+use crate::methods::{Caller, CaptchaError, CaptchaBatch, CaptchaNewDetails, CaptchaSolutionDetails, CaptchaStatus, NewResponse, RedeemResponse, Verdict};
+use crate::methods::{captcha_new, captcha_new_token, captcha_solution, captcha_newget, captcha_status, captcha_refresh, captcha_batch, captcha_redeem, captcha_audio};
+use crate::admin::{CaptchaInfo, CaptchaList, PurgeResult, captcha_inspect, captcha_revoke, captcha_list, captcha_purge};
+use crate::adaptive;
+use crate::sites::{self, SiteInfo};
+use crate::logging::{self, AuditEvent};
+
+/// Client id used if the client did not send an `X-Client-ID` header.
+pub const UNKNOWN_CLIENT: &str = "<unknown>";
+
+fn known(clientid: &str) -> Option<&str> {
+    match clientid {
+        UNKNOWN_CLIENT => None,
+        c => Some(c)
+    }
+}
+
+/// Records the error as outcome of the current request.
+fn failed(e: CaptchaError) -> CaptchaError {
+    logging::set_outcome(&format!("{:?}", e));
+    e
+}
+
+async fn difficulty_for(difficulty: String, clientid: &str, ip: Option<IpAddr>, allow_pow: bool) -> String {
+    match difficulty.as_str() {
+        adaptive::AUTO => adaptive::choose(known(clientid), ip, allow_pow).await,
+        _ => difficulty
+    }
+}
+
+// Seeds make CAPTCHAs predictable and are therefore only accepted by debug builds.
+fn debug_seed(seed: Option<u64>) -> Result<Option<u64>, CaptchaError> {
+    match seed {
+        Some(_) if !cfg!(debug_assertions) => {
+            info!("Seed rejected by release build.");
+            Err(CaptchaError::InvalidParameters)
+        },
+        s => Ok(s)
+    }
+}
+
+pub async fn req_captcha_newget(difficulty: String, seed: Option<u64>, clientid: String, ip: Option<IpAddr>) -> Result<CaptchaNewDetails, CaptchaError> {
+    let auto = difficulty == adaptive::AUTO;
+    match captcha_newget(difficulty_for(difficulty, &clientid, ip, false).await, clientid.clone(), debug_seed(seed)?).await {
         Ok(details) => {
+            logging::set_captcha(&details.uuid());
+            logging::set_outcome("created");
             info!("Created new CAPTCHA [{}], clientid [{}].", details.uuid(), clientid);
-            Ok(details.as_json())
+            if auto {
+                adaptive::record_created(known(&clientid), ip, 1).await;
+            }
+            Ok(details)
         },
         Err(e) => {
             match e {
-                CaptchaError::NotFound | CaptchaError::InvalidParameters => info!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid),
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid),
                 _ => error!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid)
             }
-            Err(e)
+            Err(failed(e))
         }
     }
 }
 
-pub fn req_captcha_new(difficulty: String, max_tries: String, ttl: String, clientid: String) -> Result<String, CaptchaError> {
-    match captcha_new(difficulty, max_tries, ttl) {
+pub async fn req_captcha_new(difficulty: String, max_tries: String, ttl: String, seed: Option<u64>, clientid: String, ip: Option<IpAddr>) -> Result<CaptchaNewDetails, CaptchaError> {
+    let seed = debug_seed(seed)?;
+    let auto = difficulty == adaptive::AUTO;
+    let difficulty = difficulty_for(difficulty, &clientid, ip, true).await;
+    created(captcha_new(difficulty, max_tries, ttl, clientid.clone(), seed).await, auto, clientid, ip).await
+}
+
+/// Logs and records the creation of a persisted CAPTCHA.
+async fn created(r: Result<CaptchaNewDetails, CaptchaError>, auto: bool, clientid: String, ip: Option<IpAddr>) -> Result<CaptchaNewDetails, CaptchaError> {
+    match r {
         Ok(details) => {
+            logging::set_captcha(&details.uuid());
+            logging::set_outcome("created");
+            logging::audit(AuditEvent::Created, &details.uuid(), None);
             info!("Created new CAPTCHA [{}], clientid [{}].", details.uuid(), clientid);
-            Ok(details.as_json())
+            if auto {
+                adaptive::record_created(known(&clientid), ip, 1).await;
+            }
+            Ok(details)
         },
         Err(e) => {
             match e {
-                CaptchaError::NotFound | CaptchaError::InvalidParameters => info!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid),
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid),
                 _ => error!("Failed to create new CAPTCHA [{:?}], clientid [{}].", e, clientid)
             }
-            Err(e)
+            Err(failed(e))
+        }
+    }
+}
+
+pub async fn req_captcha_batch(difficulty: String, count: String, max_tries: String, ttl: String, clientid: String, ip: Option<IpAddr>) -> Result<CaptchaBatch, CaptchaError> {
+    let auto = difficulty == adaptive::AUTO;
+    match captcha_batch(difficulty_for(difficulty, &clientid, ip, false).await, count, max_tries, ttl, clientid.clone()).await {
+        Ok(batch) => {
+            logging::set_outcome("created");
+            for c in batch.captchas() {
+                logging::audit(AuditEvent::Created, &c.uuid(), None);
+            }
+            info!("Created batch of [{}] CAPTCHAs, clientid [{}].", batch.len(), clientid);
+            if auto {
+                adaptive::record_created(known(&clientid), ip, batch.len() as u64).await;
+            }
+            Ok(batch)
+        },
+        Err(e) => {
+            match e {
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to create batch of CAPTCHAs [{:?}], clientid [{}].", e, clientid),
+                _ => error!("Failed to create batch of CAPTCHAs [{:?}], clientid [{}].", e, clientid)
+            }
+            Err(failed(e))
         }
     }
 }
 
-pub fn req_captcha_solution(id: String, solution: String, clientid: String) -> Result<String, CaptchaError> {
-    match captcha_solution(id, solution) {
+pub async fn req_captcha_solution(id: String, solution: String, clientid: String, ip: Option<IpAddr>) -> Result<CaptchaSolutionDetails, CaptchaError> {
+    logging::set_captcha(&id);
+    match captcha_solution(id.clone(), solution, Some(clientid.clone())).await {
         Ok(details) => {
-            info!("Solution checked for [{}] [{}], clientid [{}].", details.uuid(), details.csr().result(), clientid);
-            Ok(details.as_json())
+            let verdict = details.verdict();
+            logging::set_outcome(verdict.code());
+            logging::audit(AuditEvent::Attempt, &details.uuid(), Some(verdict.code()));
+            match verdict {
+                Verdict::Incorrect { tries_left: 0 } | Verdict::Exhausted => logging::audit(AuditEvent::Exhausted, &details.uuid(), Some(verdict.code())),
+                Verdict::Expired => logging::audit(AuditEvent::Expired, &details.uuid(), None),
+                _ => {}
+            }
+            info!("Solution checked for [{}] [{}], clientid [{}].", details.uuid(), verdict.code(), clientid);
+            match verdict {
+                Verdict::Expired | Verdict::NotFound | Verdict::AlreadyUsed => {},
+                _ => adaptive::record_checked(known(&clientid), ip, details.accepted()).await
+            }
+            Ok(details)
         },
         Err(e) => {
             match e {
-                CaptchaError::NotFound | CaptchaError::InvalidParameters => info!("Failed to check solution [{:?}], clientid [{}].", e, clientid),
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to check solution [{:?}], clientid [{}].", e, clientid),
                 _ => error!("Failed to check solution [{:?}], clientid [{}].", e, clientid)
             }
-            Err(e)
+            Err(failed(e))
+        }
+    }
+}
+
+/// Refreshes a CAPTCHA. Refreshes are requested by frontends, hence the solution is not included
+/// in the response.
+pub async fn req_captcha_refresh(id: String, clientid: String) -> Result<NewResponse, CaptchaError> {
+    match captcha_refresh(id.clone(), Some(clientid.clone())).await {
+        Ok(details) => {
+            logging::set_captcha(&details.uuid());
+            logging::set_outcome("refreshed");
+            logging::audit(AuditEvent::Created, &details.uuid(), Some("refreshed"));
+            info!("Refreshed CAPTCHA [{}] with [{}], clientid [{}].", id, details.uuid(), clientid);
+            Ok(details.response(false))
+        },
+        Err(e) => {
+            match e {
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::TooManyRefreshes | CaptchaError::Unauthorized => info!("Failed to refresh CAPTCHA [{:?}], clientid [{}].", e, clientid),
+                _ => error!("Failed to refresh CAPTCHA [{:?}], clientid [{}].", e, clientid)
+            }
+            Err(failed(e))
+        }
+    }
+}
+
+/// Creates a new CAPTCHA for the widget. Its id is a verification token, hence the solution is
+/// not included in the response.
+pub async fn req_widget_new(difficulty: String, max_tries: String, ttl: String, clientid: String, ip: Option<IpAddr>) -> Result<NewResponse, CaptchaError> {
+    let auto = difficulty == adaptive::AUTO;
+    let difficulty = difficulty_for(difficulty, &clientid, ip, true).await;
+    created(captcha_new_token(difficulty, max_tries, ttl, clientid.clone()).await, auto, clientid, ip).await
+        .map(|d| d.response(false))
+}
+
+/// Returns the public part of the site `clientid`, e.g. its branding, for the widget.
+pub async fn req_widget_site(clientid: String) -> Result<SiteInfo, CaptchaError> {
+    match sites::get(&clientid) {
+        Some(site) => Ok(site.info()),
+        None => {
+            info!("Site [{}] not found.", clientid);
+            Err(failed(CaptchaError::InvalidParameters))
+        }
+    }
+}
+
+/// Refreshes a CAPTCHA of the widget.
+pub async fn req_widget_refresh(id: String, clientid: String) -> Result<NewResponse, CaptchaError> {
+    req_captcha_refresh(id, clientid).await
+}
+
+/// Returns an audio which reads out the solution of a CAPTCHA of the widget.
+pub async fn req_widget_audio(id: String, clientid: String) -> Result<Vec<u8>, CaptchaError> {
+    logging::set_captcha(&id);
+    match captcha_audio(id.clone()).await {
+        Ok(wav) => {
+            logging::set_outcome("audio");
+            info!("Created audio for CAPTCHA [{}], clientid [{}].", id, clientid);
+            Ok(wav)
+        },
+        Err(e) => {
+            match e {
+                CaptchaError::NotFound | CaptchaError::InvalidParameters => info!("Failed to create audio [{:?}], clientid [{}].", e, clientid),
+                _ => error!("Failed to create audio [{:?}], clientid [{}].", e, clientid)
+            }
+            Err(failed(e))
+        }
+    }
+}
+
+/// Returns the status of a CAPTCHA. `backend` is false if the sender of the request has not
+/// presented the client certificate of a backend (see `tls::client_cert_required`).
+pub async fn req_captcha_status(id: String, secret: Option<String>, backend: bool, clientid: String) -> Result<CaptchaStatus, CaptchaError> {
+    logging::set_captcha(&id);
+    // Backends of sites send their secret. Other requests are sent by frontends, unless the
+    // client is not a site: its backend has no secret.
+    let caller = match secret {
+        Some(s) if backend => Caller::Backend(Some(s)),
+        None if backend && sites::get(&clientid).is_none() => Caller::Backend(None),
+        _ => Caller::Frontend(clientid.clone())
+    };
+    match captcha_status(id, caller).await {
+        Ok(status) => {
+            info!("Status queried for [{}] [{:?}], clientid [{}].", status.uuid(), status.state(), clientid);
+            Ok(status)
+        },
+        Err(e) => {
+            match e {
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to query status [{:?}], clientid [{}].", e, clientid),
+                _ => error!("Failed to query status [{:?}], clientid [{}].", e, clientid)
+            }
+            Err(failed(e))
+        }
+    }
+}
+
+/// Redeems the token of a solved CAPTCHA. Each token can be redeemed once.
+pub async fn req_captcha_redeem(id: String, secret: Option<String>, clientid: String) -> Result<RedeemResponse, CaptchaError> {
+    logging::set_captcha(&id);
+    match captcha_redeem(id, Caller::Backend(secret)).await {
+        Ok(r) => {
+            logging::set_outcome(if r.redeemed() { "redeemed" } else { "rejected" });
+            info!("Token redeemed for [{}] [{}], clientid [{}].", r.uuid(), r.redeemed(), clientid);
+            Ok(r)
+        },
+        Err(e) => {
+            match e {
+                CaptchaError::NotFound | CaptchaError::InvalidParameters | CaptchaError::Unauthorized => info!("Failed to redeem token [{:?}], clientid [{}].", e, clientid),
+                _ => error!("Failed to redeem token [{:?}], clientid [{}].", e, clientid)
+            }
+            Err(failed(e))
         }
     }
 }
+
+pub async fn req_admin_inspect(id: String, reveal: bool) -> Result<CaptchaInfo, CaptchaError> {
+    let r = captcha_inspect(id.clone(), reveal).await;
+    match r {
+        Ok(_) => info!("Admin inspected CAPTCHA [{}], reveal [{}].", id, reveal),
+        Err(ref e) => info!("Admin failed to inspect CAPTCHA [{}] [{:?}].", id, e)
+    }
+    r
+}
+
+pub async fn req_admin_revoke(id: String) -> Result<CaptchaInfo, CaptchaError> {
+    let r = captcha_revoke(id.clone()).await;
+    match r {
+        Ok(_) => info!("Admin revoked CAPTCHA [{}].", id),
+        Err(ref e) => info!("Admin failed to revoke CAPTCHA [{}] [{:?}].", id, e)
+    }
+    r
+}
+
+pub async fn req_admin_list(client: Option<String>, cursor: Option<u64>, count: Option<usize>) -> Result<CaptchaList, CaptchaError> {
+    let r = captcha_list(client.clone(), cursor, count).await;
+    match r {
+        Ok(ref l) => info!("Admin listed [{}] CAPTCHAs, client [{}].", l.len(), client.unwrap_or_default()),
+        Err(ref e) => info!("Admin failed to list CAPTCHAs [{:?}].", e)
+    }
+    r
+}
+
+pub async fn req_admin_purge(client: String) -> Result<PurgeResult, CaptchaError> {
+    let r = captcha_purge(client.clone()).await;
+    match r {
+        Ok(ref p) => info!("Admin purged [{}] CAPTCHAs, client [{}].", p.purged(), client),
+        Err(ref e) => error!("Admin failed to purge CAPTCHAs, client [{}] [{:?}].", client, e)
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::methods::{CaptchaError, NewResponse};
+    use crate::requesthandler::{req_captcha_new, req_captcha_refresh};
+
+    #[tokio::test]
+    async fn test_refresh() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        let c = req_captcha_new("easy".into(), "3".into(), "60".into(), None, "shop".into(), None).await.unwrap();
+        assert!(matches!(req_captcha_refresh(c.uuid(), "bot".into()).await, Err(CaptchaError::Unauthorized)));
+
+        // The solution of the new CAPTCHA is not revealed.
+        match req_captcha_refresh(c.uuid(), "shop".into()).await.unwrap() {
+            NewResponse::Image(r) => assert!(r.solution.is_empty()),
+            r => panic!("unexpected response {:?}", r)
+        }
+
+        env::remove_var("REDIS_HOST");
+    }
+}
@@ -0,0 +1,42 @@
+use std::env;
+use std::io::{self, Write};
+
+use crate::logging;
+
+const DEFAULT_TIMEOUT: u32 = 30;
+
+/// Maximum time in seconds to wait for in-flight requests on shutdown. Configured via the
+/// environment variable `SHUTDOWN_TIMEOUT` (default: 30).
+pub fn timeout() -> u32 {
+    env::var("SHUTDOWN_TIMEOUT").ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Flushes the logs and the audit file. The statistics are stored in Redis, so nothing else has
+/// to be flushed.
+pub fn flush() {
+    logging::flush_audit();
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use crate::shutdown::timeout;
+
+    #[test]
+    fn test_timeout() {
+        env::remove_var("SHUTDOWN_TIMEOUT");
+        assert_eq!(timeout(), 30);
+
+        env::set_var("SHUTDOWN_TIMEOUT", "5");
+        assert_eq!(timeout(), 5);
+
+        env::set_var("SHUTDOWN_TIMEOUT", "x");
+        assert_eq!(timeout(), 30);
+
+        env::remove_var("SHUTDOWN_TIMEOUT");
+    }
+}
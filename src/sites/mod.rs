@@ -0,0 +1,365 @@
+//! Registry of the sites which use the service.
+//!
+//! A site is identified by its public key, which is sent in the header `X-Client-ID`, e.g. by
+//! the widget. Its secret is only known to the backend of the site and is needed to query the
+//! status of the CAPTCHAs of the site. The registry is loaded at startup from the JSON file in
+//! the environment variable `SITES_FILE`:
+//!
+//! ```json
+//! [{
+//!   "key": "shop",
+//!   "secret": "...",
+//!   "origins": ["https://shop.example"],
+//!   "defaults": { "difficulty": "medium", "max_tries": 3, "ttl": 300 },
+//!   "limits": { "difficulty": "hard", "max_tries": 5, "ttl": 600 },
+//!   "branding": { "name": "Shop", "color": "#0a7d3b" },
+//!   "theme": { "width": 300, "height": 100, "background": "#202124", "foreground": "#e0e0e0" }
+//! }]
+//! ```
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::sync::RwLock;
+
+use crate::admin::constant_time_eq;
+use crate::methods::{CaptchaError, ANIMATED};
+use crate::theme::Theme;
+use crate::validation::{validate_tries, validate_ttl};
+
+/// Value of a parameter of a new CAPTCHA which selects the default of the site.
+pub const DEFAULT: &str = "default";
+
+/// Defaults for clients which are not in the registry.
+const DEFAULT_DIFFICULTY: &str = "medium";
+const DEFAULT_TRIES: usize = 3;
+const DEFAULT_TTL: i64 = 300;
+
+const DIFFICULTIES: [&str; 3] = ["easy", "medium", "hard"];
+
+static REGISTRY: RwLock<Vec<Site>> = RwLock::new(Vec::new());
+
+/// Defaults or maximums of the parameters of new CAPTCHAs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub difficulty: Option<String>,
+    pub max_tries: Option<usize>,
+    pub ttl: Option<i64>,
+}
+
+/// Options which change the appearance of the widget.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Branding {
+    /// Name of the site, shown by the widget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Accent color of the widget, e.g. `#0a7d3b`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// URL of a logo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Site {
+    key: String,
+    secret: String,
+    #[serde(default)]
+    origins: Vec<String>,
+    #[serde(default)]
+    defaults: Settings,
+    #[serde(default)]
+    limits: Settings,
+    #[serde(default)]
+    branding: Branding,
+    #[serde(default)]
+    theme: Option<Theme>,
+}
+
+/// The public part of a site which is sent to the widget.
+#[derive(Serialize, Debug)]
+pub struct SiteInfo {
+    key: String,
+    branding: Branding,
+}
+
+impl Site {
+    pub fn new(key: &str, secret: &str) -> Site {
+        Site {
+            key: key.to_string(),
+            secret: secret.to_string(),
+            origins: vec![],
+            defaults: Settings::default(),
+            limits: Settings::default(),
+            branding: Branding::default(),
+            theme: None,
+        }
+    }
+
+    pub fn origins(mut self, origins: &[&str]) -> Site {
+        self.origins = origins.iter().map(|o| o.to_string()).collect();
+        self
+    }
+
+    pub fn defaults(mut self, defaults: Settings) -> Site {
+        self.defaults = defaults;
+        self
+    }
+
+    pub fn limits(mut self, limits: Settings) -> Site {
+        self.limits = limits;
+        self
+    }
+
+    pub fn branding(mut self, branding: Branding) -> Site {
+        self.branding = branding;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Site {
+        self.theme = Some(theme);
+        self
+    }
+
+    pub fn theme(&self) -> Option<Theme> {
+        self.theme.clone()
+    }
+
+    pub fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    /// Origins which may call the service for this site.
+    pub fn allowed_origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    pub fn info(&self) -> SiteInfo {
+        SiteInfo { key: self.key.clone(), branding: self.branding.clone() }
+    }
+
+    /// Checks the secret of the site in constant time.
+    pub fn authorized(&self, secret: &str) -> bool {
+        constant_time_eq(self.secret.as_bytes(), secret.as_bytes())
+    }
+
+    /// Checks the site and loads the font of its theme.
+    fn load(&mut self) -> Result<(), String> {
+        if self.key.is_empty() || self.key.len() > 100 {
+            return Err(format!("invalid key [{}]", self.key));
+        }
+        if self.secret.is_empty() {
+            return Err(format!("empty secret for site [{}]", self.key));
+        }
+        for s in &[&self.defaults, &self.limits] {
+            if let Some(ref d) = s.difficulty {
+                if rank(d).is_none() {
+                    return Err(format!("invalid difficulty [{}] for site [{}]", d, self.key));
+                }
+            }
+        }
+        match self.theme {
+            Some(ref mut t) => t.load().map_err(|e| format!("{} for site [{}]", e, self.key)),
+            None => Ok(())
+        }
+    }
+}
+
+fn rank(difficulty: &str) -> Option<usize> {
+    DIFFICULTIES.iter().position(|d| *d == difficulty)
+}
+
+/// Parses a registry and checks that all sites are valid and that keys are unique.
+pub fn parse(json: &str) -> Result<Vec<Site>, String> {
+    let mut sites: Vec<Site> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for s in sites.iter_mut() {
+        s.load()?;
+    }
+    let mut keys = HashSet::new();
+    for s in &sites {
+        if !keys.insert(s.key.as_str()) {
+            return Err(format!("duplicate key [{}]", s.key));
+        }
+    }
+    Ok(sites)
+}
+
+/// Loads the registry from the file in the environment variable `SITES_FILE`. Returns the
+/// number of sites. If the variable is not set, the registry is empty.
+pub fn load() -> Result<usize, String> {
+    let path = match env::var("SITES_FILE") {
+        Ok(p) => p,
+        Err(_) => return Ok(0)
+    };
+    let json = fs::read_to_string(&path).map_err(|e| format!("cannot read [{}] [{}]", path, e))?;
+    let sites = parse(&json)?;
+    let n = sites.len();
+    set(sites);
+    Ok(n)
+}
+
+/// Replaces the registry.
+pub fn set(sites: Vec<Site>) {
+    *REGISTRY.write().unwrap_or_else(|e| e.into_inner()) = sites;
+}
+
+/// Returns the site with the given key.
+pub fn get(key: &str) -> Option<Site> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|s| s.key == key)
+        .cloned()
+}
+
+/// Checks the secret which a backend has sent for a CAPTCHA of the client `client`. Clients which
+/// are not registered sites have no secret, so their CAPTCHAs need none. The CAPTCHAs of a site
+/// need its secret.
+pub fn authorized(client: &str, secret: Option<&str>) -> bool {
+    match get(client) {
+        Some(site) => secret.is_some_and(|s| site.authorized(s)),
+        None => true
+    }
+}
+
+/// Returns all sites.
+pub fn all() -> Vec<Site> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Returns true if only registered sites may create CAPTCHAs. Configured via the environment
+/// variable `SITES_REQUIRED` (default: false).
+pub fn required() -> bool {
+    env::var("SITES_REQUIRED").map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+/// Applies the defaults and limits of the site `clientid` to the parameters of a new CAPTCHA.
+///
+/// A parameter with the value `default` is replaced by the default of the site or, if the
+/// client is not a registered site, by the global default. Values above the limits of the site
+/// are lowered to the limits, the difficulty of animated CAPTCHAs, too. Proof-of-work challenges
+/// are not limited.
+pub fn apply(clientid: &str, difficulty: String, max_tries: String, ttl: String) -> Result<(String, usize, i64), CaptchaError> {
+    let site = get(clientid);
+    if site.is_none() && required() {
+        info!("Client [{}] is not a registered site.", clientid);
+        return Err(CaptchaError::Unauthorized);
+    }
+    let (defaults, limits) = match site {
+        Some(s) => (s.defaults, s.limits),
+        None => (Settings::default(), Settings::default())
+    };
+
+    let mut d = match difficulty.as_str() {
+        DEFAULT => defaults.difficulty.unwrap_or_else(|| DEFAULT_DIFFICULTY.to_string()),
+        _ => difficulty
+    };
+    // Animated CAPTCHAs are limited like image CAPTCHAs.
+    let (prefix, base) = match d.strip_prefix(ANIMATED) {
+        Some(b) => (ANIMATED, b.to_string()),
+        None => ("", d.clone())
+    };
+    if let (Some(r), Some(m)) = (rank(&base), limits.difficulty.as_deref().and_then(rank)) {
+        if r > m {
+            d = format!("{}{}", prefix, DIFFICULTIES[m]);
+        }
+    }
+
+    let x = match max_tries.as_str() {
+        DEFAULT => defaults.max_tries.unwrap_or(DEFAULT_TRIES),
+        _ => validate_tries(max_tries)?
+    };
+    let t = match ttl.as_str() {
+        DEFAULT => defaults.ttl.unwrap_or(DEFAULT_TTL),
+        _ => validate_ttl(ttl)?
+    };
+    Ok((d, limits.max_tries.map_or(x, |m| x.min(m)), limits.ttl.map_or(t, |m| t.min(m))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::requesthandler::{req_captcha_new, req_captcha_status};
+    use crate::methods::{captcha_new, captcha_newget, captcha_solution, captcha_status, Caller, CaptchaError, CaptchaState};
+    use crate::sites::{apply, authorized, parse, set, Settings, Site};
+
+    #[test]
+    fn test_parse() {
+        let sites = parse(r#"[{"key": "shop", "secret": "s", "origins": ["https://shop.example"], "branding": {"name": "Shop"}}]"#).unwrap();
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].key(), "shop");
+        assert_eq!(sites[0].allowed_origins(), &[String::from("https://shop.example")]);
+        assert!(sites[0].authorized("s"));
+        assert!(!sites[0].authorized("x"));
+
+        assert!(parse(r#"[{"key": "a", "secret": ""}]"#).is_err());
+        assert!(parse(r#"[{"key": "a", "secret": "s"}, {"key": "a", "secret": "t"}]"#).is_err());
+        assert!(parse(r#"[{"key": "a", "secret": "s", "limits": {"difficulty": "extreme"}}]"#).is_err());
+    }
+
+    // The registry is global, hence all tests which change it are in this function.
+    #[tokio::test]
+    async fn test_registry() {
+        env::set_var("REDIS_HOST", "localhost");
+
+        set(vec![Site::new("test-site", "secret")
+            .defaults(Settings { difficulty: Some("easy".into()), max_tries: Some(2), ttl: Some(60) })
+            .limits(Settings { difficulty: Some("medium".into()), max_tries: Some(5), ttl: Some(120) })]);
+
+        let r = apply("test-site", "default".into(), "default".into(), "default".into()).unwrap();
+        assert_eq!(r, (String::from("easy"), 2, 60));
+        let r = apply("test-site", "hard".into(), "9".into(), "900".into()).unwrap();
+        assert_eq!(r, (String::from("medium"), 5, 120));
+        let r = apply("test-site", "pow-20".into(), "1".into(), "30".into()).unwrap();
+        assert_eq!(r, (String::from("pow-20"), 1, 30));
+        let r = apply("test-site", "animated-hard".into(), "1".into(), "30".into()).unwrap();
+        assert_eq!(r, (String::from("animated-medium"), 1, 30));
+
+        // Clients which are not registered use the global defaults and have no limits.
+        let r = apply("other", "default".into(), "default".into(), "900".into()).unwrap();
+        assert_eq!(r, (String::from("medium"), 3, 900));
+        assert!(apply("other", "default".into(), "x".into(), "60".into()).is_err());
+
+        // A CAPTCHA of a site can only be solved by the site and its status needs the secret.
+        let c = captcha_new("hard".into(), "9".into(), "60".into(), "test-site".into(), None).await.unwrap();
+        assert_eq!(c.tries_left(), Some(5));
+        let solution = c.solution().unwrap().to_string();
+        assert!(captcha_solution(c.uuid(), solution.clone(), Some("other".into())).await.is_err());
+        assert!(captcha_status(c.uuid(), Caller::Backend(Some("wrong".into()))).await.is_err());
+        assert!(captcha_status(c.uuid(), Caller::Backend(None)).await.is_err());
+        assert!(captcha_solution(c.uuid(), solution, Some("test-site".into())).await.unwrap().accepted());
+        assert!(captcha_status(c.uuid(), Caller::Backend(Some("wrong".into()))).await.is_err());
+        assert_eq!(captcha_status(c.uuid(), Caller::Backend(Some("secret".into()))).await.unwrap().state(), CaptchaState::Solved);
+
+        // CAPTCHAs of other clients need no secret.
+        assert!(authorized("other", None));
+        assert!(!authorized("test-site", None));
+
+        // The frontend of a site queries the status of its CAPTCHAs without the secret and
+        // without a client certificate, but cannot tell whether they have been solved.
+        let c = req_captcha_new("easy".into(), "3".into(), "60".into(), None, "test-site".into(), None).await.unwrap();
+        let s = req_captcha_status(c.uuid(), None, false, "test-site".into()).await.unwrap();
+        assert_eq!((s.state(), s.tries_left()), (CaptchaState::Pending, 3));
+        assert!(matches!(req_captcha_status(c.uuid(), None, true, "other".into()).await, Err(CaptchaError::Unauthorized)));
+        let solution = c.solution().unwrap().to_string();
+        assert!(captcha_solution(c.uuid(), solution, None).await.unwrap().accepted());
+        assert!(matches!(req_captcha_status(c.uuid(), None, true, "test-site".into()).await, Err(CaptchaError::Unauthorized)));
+        assert!(matches!(req_captcha_status(c.uuid(), Some("secret".into()), false, "test-site".into()).await, Err(CaptchaError::Unauthorized)));
+        let s = req_captcha_status(c.uuid(), Some("secret".into()), true, "test-site".into()).await.unwrap();
+        assert_eq!(s.state(), CaptchaState::Solved);
+
+        // CAPTCHAs which are not persisted get the defaults of the site, too.
+        let c = captcha_newget("default".into(), "test-site".into(), Some(1)).await.unwrap();
+        assert_eq!(c.solution().unwrap().len(), 4);
+
+        // Only sites may create CAPTCHAs if sites are required.
+        env::set_var("SITES_REQUIRED", "true");
+        let r = captcha_newget("easy".into(), "other".into(), None).await;
+        env::remove_var("SITES_REQUIRED");
+        assert!(matches!(r, Err(CaptchaError::Unauthorized)));
+
+        set(vec![]);
+        env::remove_var("REDIS_HOST");
+    }
+}
@@ -0,0 +1,261 @@
+//! Themes change the size and the colors of image CAPTCHAs and the font of their characters.
+//! A theme is configured per site (see `sites`):
+//!
+//! ```json
+//! "theme": { "width": 300, "height": 100, "foreground": "#e0e0e0", "background": "#202124", "font": "/fonts/Mono.ttf" }
+//! ```
+//!
+//! Fonts are TrueType or OpenType files which are loaded from disk. They can be bundled by
+//! copying them into the image of the service.
+
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use ab_glyph::{Font, FontVec, PxScale};
+use image::{GrayImage, ImageOutputFormat, Luma, Rgb, RgbImage};
+
+/// Size of the images if the theme does not set it.
+pub const DEFAULT_WIDTH: u32 = 220;
+pub const DEFAULT_HEIGHT: u32 = 120;
+
+// The characters are drawn on a canvas of 400x300 pixels which is cropped to the size of the
+// image, hence the size cannot exceed the canvas.
+const MIN_WIDTH: u32 = 100;
+const MAX_WIDTH: u32 = 400;
+const MIN_HEIGHT: u32 = 50;
+const MAX_HEIGHT: u32 = 300;
+
+/// Characters of a font. They are the characters of the default font which cannot be confused
+/// with each other.
+pub const CHARS: &str = "123456789ABCDEFGHJKMNPQRSTUVWXYZabcdefghijklmnpqrstuvwxyz";
+
+/// Line height of a font in pixels. Capital letters are then about 40 pixels high like those of
+/// the default font.
+const LINE_HEIGHT: f32 = 52.0;
+
+/// Fonts which have been loaded, by path.
+static FONTS: Mutex<Vec<(String, Arc<TtfFont>)>> = Mutex::new(Vec::new());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Theme {
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Color of the characters, e.g. `#e0e0e0`.
+    #[serde(default)]
+    pub foreground: Option<String>,
+    /// Color of the background, e.g. `#202124`.
+    #[serde(default)]
+    pub background: Option<String>,
+    /// Path of a TTF or OTF file.
+    #[serde(default)]
+    pub font: Option<String>,
+    /// The font which has been loaded by `load`.
+    #[serde(skip)]
+    pub(crate) loaded: Option<SharedFont>,
+}
+
+impl Theme {
+    pub fn width(&self) -> u32 {
+        self.width.unwrap_or(DEFAULT_WIDTH)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height.unwrap_or(DEFAULT_HEIGHT)
+    }
+
+    /// Returns the font of the theme if it has been loaded by `load`.
+    pub fn font(&self) -> Option<&SharedFont> {
+        self.loaded.as_ref()
+    }
+
+    /// Checks the theme and loads its font.
+    pub fn load(&mut self) -> Result<(), String> {
+        self.check()?;
+        self.loaded = match self.font {
+            Some(ref p) => Some(load_font(p)?),
+            None => None
+        };
+        Ok(())
+    }
+
+    /// Checks the size and the colors and loads the font.
+    pub fn check(&self) -> Result<(), String> {
+        if !(MIN_WIDTH..=MAX_WIDTH).contains(&self.width()) || !(MIN_HEIGHT..=MAX_HEIGHT).contains(&self.height()) {
+            return Err(format!("invalid size [{}x{}]", self.width(), self.height()));
+        }
+        for c in self.foreground.iter().chain(self.background.iter()) {
+            color(c).ok_or_else(|| format!("invalid color [{}]", c))?;
+        }
+        match self.font {
+            Some(ref p) => load_font(p).map(|_| ()),
+            None => Ok(())
+        }
+    }
+
+    /// Changes the colors of an image CAPTCHA. The CAPTCHA consists of black characters on a
+    /// white background. Gray pixels, e.g. of filters, are mixed from both colors.
+    pub fn colorize(&self, img: &mut RgbImage) {
+        let fg = self.foreground.as_deref().and_then(color);
+        let bg = self.background.as_deref().and_then(color);
+        if fg.is_none() && bg.is_none() {
+            return;
+        }
+        let (fg, bg) = (fg.unwrap_or([0, 0, 0]), bg.unwrap_or([255, 255, 255]));
+        for p in img.pixels_mut() {
+            let l = p.0[0] as u32;
+            for i in 0..3 {
+                p.0[i] = ((fg[i] as u32 * (255 - l) + bg[i] as u32 * l) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Parses a color in the format `#rrggbb`.
+pub fn color(s: &str) -> Option<[u8; 3]> {
+    let hex = s.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let c = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([c(0)?, c(2)?, c(4)?])
+}
+
+pub(crate) fn encode_png(img: RgbImage) -> Option<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(img).write_to(&mut out, ImageOutputFormat::Png).ok()?;
+    Some(out.into_inner())
+}
+
+/// The characters of a TTF font as black and white images.
+pub struct TtfFont {
+    glyphs: Vec<(char, GrayImage)>,
+}
+
+impl TtfFont {
+    /// Renders the characters of the font.
+    pub fn parse(data: Vec<u8>) -> Result<TtfFont, String> {
+        let font = FontVec::try_from_vec(data).map_err(|e| e.to_string())?;
+        let scale = PxScale::from(LINE_HEIGHT);
+        let mut glyphs = vec![];
+        for c in CHARS.chars() {
+            let glyph = font.glyph_id(c).with_scale(scale);
+            let outline = match font.outline_glyph(glyph) {
+                Some(o) => o,
+                None => return Err(format!("character [{}] is missing", c))
+            };
+            let bounds = outline.px_bounds();
+            let mut img = GrayImage::from_pixel(bounds.width().ceil() as u32 + 2, bounds.height().ceil() as u32 + 2, Luma([255]));
+            // The filters expect black and white images.
+            outline.draw(|x, y, coverage| {
+                if coverage > 0.5 {
+                    img.put_pixel(x + 1, y + 1, Luma([0]));
+                }
+            });
+            glyphs.push((c, img));
+        }
+        Ok(TtfFont { glyphs })
+    }
+
+    /// Draws `text` in black on a white image of the given size. Returns `None` if the font does
+    /// not have a character.
+    pub fn draw(&self, text: &str, width: u32, height: u32) -> Option<RgbImage> {
+        let glyphs = text.chars()
+            .map(|c| self.glyphs.iter().find(|(g, _)| *g == c).map(|(_, i)| i))
+            .collect::<Option<Vec<_>>>()?;
+        Some(place(&glyphs, width, height))
+    }
+}
+
+/// Draws images of characters side by side in black on a white image of the given size. Like in
+/// the `captcha` crate, the text is centered. It is cut off if it does not fit.
+pub(crate) fn place(glyphs: &[&GrayImage], width: u32, height: u32) -> RgbImage {
+    let total: u32 = glyphs.iter().map(|g| g.width()).sum();
+    let mut img = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    let mut x0 = (width as i64 - total as i64) / 2;
+    for g in glyphs {
+        let y0 = (height as i64 - g.height() as i64) / 2;
+        for (x, y, p) in g.enumerate_pixels() {
+            let (px, py) = (x0 + x as i64, y0 + y as i64);
+            if p.0[0] < 128 && px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+                img.put_pixel(px as u32, py as u32, Rgb([0, 0, 0]));
+            }
+        }
+        x0 += g.width() as i64;
+    }
+    img
+}
+
+/// A loaded font which is shared by the themes which use it.
+#[derive(Clone)]
+pub struct SharedFont(Arc<TtfFont>);
+
+impl SharedFont {
+    pub fn draw(&self, text: &str, width: u32, height: u32) -> Option<RgbImage> {
+        self.0.draw(text, width, height)
+    }
+}
+
+impl fmt::Debug for SharedFont {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedFont({} glyphs)", self.0.glyphs.len())
+    }
+}
+
+impl PartialEq for SharedFont {
+    fn eq(&self, other: &SharedFont) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Loads the font at `path` or returns it from the cache.
+pub fn load_font(path: &str) -> Result<SharedFont, String> {
+    let mut fonts = FONTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, f)) = fonts.iter().find(|(p, _)| p == path) {
+        return Ok(SharedFont(f.clone()));
+    }
+    let data = fs::read(path).map_err(|e| format!("cannot read [{}] [{}]", path, e))?;
+    let font = Arc::new(TtfFont::parse(data).map_err(|e| format!("invalid font [{}] [{}]", path, e))?);
+    fonts.push((path.to_string(), font.clone()));
+    Ok(SharedFont(font))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use crate::theme::{color, Theme};
+
+    #[test]
+    fn test_color() {
+        assert_eq!(color("#202124"), Some([0x20, 0x21, 0x24]));
+        assert_eq!(color("#FFffFF"), Some([255, 255, 255]));
+        assert_eq!(color("202124"), None);
+        assert_eq!(color("#2021"), None);
+        assert_eq!(color("#20212x"), None);
+    }
+
+    #[test]
+    fn test_colorize() {
+        let mut img = RgbImage::from_pixel(4, 2, Rgb([255, 255, 255]));
+        img.put_pixel(1, 1, Rgb([0, 0, 0]));
+        img.put_pixel(2, 1, Rgb([128, 128, 128]));
+        let theme = Theme { foreground: Some("#e0e0e0".into()), background: Some("#202020".into()), ..Theme::default() };
+        theme.colorize(&mut img);
+        assert_eq!(img.get_pixel(0, 0), &Rgb([0x20, 0x20, 0x20]));
+        assert_eq!(img.get_pixel(1, 1), &Rgb([0xe0, 0xe0, 0xe0]));
+        assert_eq!(img.get_pixel(2, 1), &Rgb([0x7f, 0x7f, 0x7f]));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(Theme::default().check().is_ok());
+        assert!(Theme { width: Some(300), height: Some(100), ..Theme::default() }.check().is_ok());
+        assert!(Theme { width: Some(500), ..Theme::default() }.check().is_err());
+        assert!(Theme { background: Some("black".into()), ..Theme::default() }.check().is_err());
+        assert!(Theme { font: Some("/nonexistent.ttf".into()), ..Theme::default() }.check().is_err());
+        let mut t = Theme { font: Some("/nonexistent.ttf".into()), ..Theme::default() };
+        assert!(t.load().is_err());
+        assert!(t.font().is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::BufReader;
+use std::time::SystemTime;
+
+use rustls::{Certificate, PrivateKey, RootCertStore, SignatureScheme};
+use rustls_pemfile::Item;
+use webpki::{EndEntityCert, SignatureAlgorithm};
+
+/// Route groups which can require client certificates.
+pub const ADMIN: &str = "admin";
+pub const VERIFY: &str = "verify";
+
+const DEFAULT_RELOAD_INTERVAL: u64 = 60;
+
+/// TLS settings of the server. They are read from the environment variables `TLS_CERT`,
+/// `TLS_KEY` and `TLS_CLIENT_CA`, which contain the paths of PEM files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub cert: String,
+    pub key: String,
+    /// CA which signs the client certificates of the routes in `TLS_CLIENT_CERT_ROUTES`.
+    pub client_ca: Option<String>,
+}
+
+impl Settings {
+    /// Returns the files of the settings.
+    pub fn files(&self) -> Vec<&str> {
+        let mut r = vec![self.cert.as_str(), self.key.as_str()];
+        r.extend(self.client_ca.as_deref());
+        r
+    }
+}
+
+/// Returns the TLS settings or `None` if TLS is disabled. An error is returned if only one of
+/// `TLS_CERT` and `TLS_KEY` is set, if `TLS_CLIENT_CA` is set without them or if a file cannot
+/// be read.
+pub fn settings() -> Result<Option<Settings>, String> {
+    settings_from(|k| env::var(k).ok())
+}
+
+/// Returns the TLS settings from the variables returned by `var`.
+fn settings_from<F: Fn(&str) -> Option<String>>(var: F) -> Result<Option<Settings>, String> {
+    let client_ca = var("TLS_CLIENT_CA");
+    let s = match (var("TLS_CERT"), var("TLS_KEY")) {
+        (None, None) if client_ca.is_none() => return Ok(None),
+        (Some(cert), Some(key)) => Settings { cert, key, client_ca },
+        (None, None) => return Err(String::from("TLS_CLIENT_CA requires TLS_CERT and TLS_KEY")),
+        _ => return Err(String::from("TLS_CERT and TLS_KEY must both be set to enable TLS"))
+    };
+    for path in s.files() {
+        if let Err(e) = fs::File::open(path) {
+            return Err(format!("cannot read [{}] [{}]", path, e));
+        }
+    }
+    Ok(Some(s))
+}
+
+/// Signature schemes which are used to check that the private key belongs to the certificate.
+const SCHEMES: [(SignatureScheme, &SignatureAlgorithm); 4] = [
+    (SignatureScheme::ECDSA_NISTP256_SHA256, &webpki::ECDSA_P256_SHA256),
+    (SignatureScheme::ECDSA_NISTP384_SHA384, &webpki::ECDSA_P384_SHA384),
+    (SignatureScheme::ED25519, &webpki::ED25519),
+    (SignatureScheme::RSA_PSS_SHA256, &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY),
+];
+
+/// Checks whether the files of the settings contain a certificate chain, the private key of its
+/// first certificate and a client CA, so that a server can be started with them.
+pub fn validate(s: &Settings) -> Result<(), String> {
+    let certs = pem(&s.cert)?.into_iter()
+        .filter_map(|i| match i {
+            Item::X509Certificate(c) => Some(Certificate(c)),
+            _ => None
+        })
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(format!("no certificate in [{}]", s.cert));
+    }
+    let key = pem(&s.key)?.into_iter()
+        .find_map(|i| match i {
+            Item::RSAKey(k) | Item::PKCS8Key(k) | Item::ECKey(k) => Some(PrivateKey(k)),
+            _ => None
+        })
+        .ok_or_else(|| format!("no private key in [{}]", s.key))?;
+    // The key belongs to the certificate if the certificate verifies a signature of the key.
+    let signer = rustls::sign::any_supported_type(&key)
+        .map_err(|e| format!("invalid private key in [{}] [{}]", s.key, e))?
+        .choose_scheme(&SCHEMES.map(|(s, _)| s))
+        .ok_or_else(|| format!("unsupported private key in [{}]", s.key))?;
+    let alg = SCHEMES.iter()
+        .find(|(s, _)| *s == signer.scheme())
+        .map(|(_, a)| *a)
+        .ok_or_else(|| format!("unsupported private key in [{}]", s.key))?;
+    let msg = b"rust-captcha";
+    let signature = signer.sign(msg).map_err(|e| format!("invalid private key in [{}] [{}]", s.key, e))?;
+    EndEntityCert::try_from(certs[0].0.as_slice())
+        .map_err(|e| format!("invalid certificate in [{}] [{:?}]", s.cert, e))?
+        .verify_signature(alg, msg, &signature)
+        .map_err(|_| format!("the key [{}] does not belong to the certificate [{}]", s.key, s.cert))?;
+    if let Some(ref ca) = s.client_ca {
+        let mut roots = RootCertStore::empty();
+        for i in pem(ca)? {
+            if let Item::X509Certificate(c) = i {
+                roots.add(&Certificate(c)).map_err(|e| format!("invalid CA certificate in [{}] [{}]", ca, e))?;
+            }
+        }
+        if roots.is_empty() {
+            return Err(format!("no CA certificate in [{}]", ca));
+        }
+    }
+    Ok(())
+}
+
+fn pem(path: &str) -> Result<Vec<Item>, String> {
+    let f = fs::File::open(path).map_err(|e| format!("cannot read [{}] [{}]", path, e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(f)).map_err(|e| format!("cannot parse [{}] [{}]", path, e))
+}
+
+/// Returns whether the routes of `group` require a client certificate. Configured via the
+/// environment variable `TLS_CLIENT_CERT_ROUTES` which contains a comma separated list of
+/// groups (default: `admin`). Client certificates are only required if `TLS_CLIENT_CA` is set.
+pub fn client_cert_required(group: &str) -> bool {
+    client_cert_required_from(group, |k| env::var(k).ok())
+}
+
+fn client_cert_required_from<F: Fn(&str) -> Option<String>>(group: &str, var: F) -> bool {
+    var("TLS_CLIENT_CA").is_some() && var("TLS_CLIENT_CERT_ROUTES")
+        .unwrap_or_else(|| String::from(ADMIN))
+        .split(',')
+        .any(|g| g.trim().eq_ignore_ascii_case(group))
+}
+
+/// Seconds between two checks whether the files of the settings have changed. Configured via
+/// the environment variable `TLS_RELOAD_INTERVAL` (default: 60). `0` disables the reload.
+pub fn reload_interval() -> u64 {
+    env::var("TLS_RELOAD_INTERVAL").ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RELOAD_INTERVAL)
+}
+
+/// Returns the modification times of the files. Files which cannot be read have no time.
+pub fn modified(files: &[&str]) -> Vec<Option<SystemTime>> {
+    files.iter()
+        .map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+
+    use crate::tls::{client_cert_required_from, modified, reload_interval, settings_from, validate, Settings, ADMIN, VERIFY};
+
+    fn testdata(name: &str) -> String {
+        format!("{}/src/tls/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Returns a lookup of the given variables instead of the environment.
+    fn vars(v: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let v: HashMap<String, String> = v.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |k| v.get(k).cloned()
+    }
+
+    #[test]
+    fn test_settings() {
+        let (cert, missing) = (testdata("cert.pem"), testdata("missing.pem"));
+
+        assert_eq!(settings_from(vars(&[])), Ok(None));
+        assert!(settings_from(vars(&[("TLS_CERT", &cert)])).is_err());
+        assert_eq!(settings_from(vars(&[("TLS_CERT", &cert), ("TLS_KEY", &cert)])),
+                   Ok(Some(Settings { cert: cert.clone(), key: cert.clone(), client_ca: None })));
+        assert!(settings_from(vars(&[("TLS_CERT", &cert), ("TLS_KEY", &cert), ("TLS_CLIENT_CA", &missing)])).is_err());
+        assert!(settings_from(vars(&[("TLS_CLIENT_CA", &cert)])).is_err());
+
+        let s = Settings { cert: cert.clone(), key: cert.clone(), client_ca: None };
+        assert_eq!(modified(&s.files()), modified(&[&cert, &cert]));
+        assert!(modified(&s.files())[0].is_some());
+        assert_eq!(modified(&[&missing]), vec![None]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let (cert, key) = (testdata("cert.pem"), testdata("key.pem"));
+        assert_eq!(validate(&Settings { cert: cert.clone(), key: key.clone(), client_ca: None }), Ok(()));
+        assert_eq!(validate(&Settings { cert: cert.clone(), key: key.clone(), client_ca: Some(cert.clone()) }), Ok(()));
+
+        // The key of another certificate, a missing key and an incomplete file.
+        assert!(validate(&Settings { cert: cert.clone(), key: testdata("other-key.pem"), client_ca: None }).is_err());
+        assert!(validate(&Settings { cert: cert.clone(), key: cert.clone(), client_ca: None }).is_err());
+        assert!(validate(&Settings { cert: key.clone(), key: key.clone(), client_ca: None }).is_err());
+        assert!(validate(&Settings { cert: cert.clone(), key: key.clone(), client_ca: Some(key.clone()) }).is_err());
+        assert!(validate(&Settings { cert: cert.clone(), key: testdata("missing.pem"), client_ca: None }).is_err());
+    }
+
+    #[test]
+    fn test_client_cert_required() {
+        assert!(!client_cert_required_from(ADMIN, vars(&[])));
+
+        let ca = [("TLS_CLIENT_CA", "ca.pem")];
+        assert!(client_cert_required_from(ADMIN, vars(&ca)));
+        assert!(!client_cert_required_from(VERIFY, vars(&ca)));
+        let routes = [("TLS_CLIENT_CA", "ca.pem"), ("TLS_CLIENT_CERT_ROUTES", "admin, Verify")];
+        assert!(client_cert_required_from(VERIFY, vars(&routes)));
+        assert!(!client_cert_required_from(VERIFY, vars(&routes[1..])));
+
+        if env::var("TLS_RELOAD_INTERVAL").is_err() {
+            assert_eq!(reload_interval(), 60);
+        }
+    }
+}
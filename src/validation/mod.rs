@@ -1,19 +1,39 @@
 use std::str::FromStr;
 
 use captcha::Difficulty;
-use methods::CaptchaError;
+use crate::methods::{CaptchaError, Challenge, ANIMATED};
+use crate::pow::MAX_BITS;
 
 use uuid::Uuid;
 
 pub fn validate_difficulty(s: String) -> Result<Difficulty, CaptchaError> {
     match s.as_str() {
-        "easy"   => { return Ok(Difficulty::Easy); },
-        "medium" => { return Ok(Difficulty::Medium); },
-        "hard"   => { return Ok(Difficulty::Hard); }
-        _        => { return Err(CaptchaError::InvalidParameters); }
+        "easy"   => Ok(Difficulty::Easy),
+        "medium" => Ok(Difficulty::Medium),
+        "hard"   => Ok(Difficulty::Hard),
+        _        => Err(CaptchaError::InvalidParameters)
     }
 }
 
+/// Validates the type of a challenge. Besides the difficulties of image CAPTCHAs, `pow` for a
+/// proof-of-work challenge with `pow_bits` leading zero bits, `pow-<bits>` and
+/// `animated-<difficulty>` for an animated CAPTCHA are accepted.
+pub fn validate_challenge(s: String, pow_bits: u32) -> Result<Challenge, CaptchaError> {
+    if let Some(d) = s.strip_prefix(ANIMATED) {
+        return validate_difficulty(d.to_string()).map(Challenge::Animated);
+    }
+    if s == "pow" {
+        return Ok(Challenge::ProofOfWork(pow_bits));
+    }
+    if s.starts_with("pow-") && s.len() <= 6 {
+        return match s[4..].parse::<u32>() {
+            Ok(bits) if bits > 0 && bits <= MAX_BITS => Ok(Challenge::ProofOfWork(bits)),
+            _ => Err(CaptchaError::InvalidParameters)
+        };
+    }
+    validate_difficulty(s).map(Challenge::Image)
+}
+
 pub fn validate_tries(s: String) -> Result<usize, CaptchaError> {
     if s.len() > 3 {
         return Err(CaptchaError::InvalidParameters);
@@ -21,6 +41,13 @@ pub fn validate_tries(s: String) -> Result<usize, CaptchaError> {
     s.parse::<usize>().map_err(|_| CaptchaError::InvalidParameters)
 }
 
+pub fn validate_batch_size(s: String, max: usize) -> Result<usize, CaptchaError> {
+    match validate_tries(s)? {
+        n if n > 0 && n <= max => Ok(n),
+        _ => Err(CaptchaError::InvalidParameters)
+    }
+}
+
 pub fn validate_ttl(s: String) -> Result<i64, CaptchaError> {
     Ok(validate_tries(s)? as i64)
 }
@@ -32,6 +59,21 @@ pub fn validate_id(s: String) -> Result<Uuid, CaptchaError> {
     Uuid::from_str(s.as_str()).map_err(|_| CaptchaError::InvalidParameters)
 }
 
+pub fn validate_client(s: String) -> Result<String, CaptchaError> {
+    if s.is_empty() || s.len() > 100 {
+        return Err(CaptchaError::InvalidParameters);
+    }
+    Ok(s)
+}
+
+pub fn validate_count(n: Option<usize>) -> Result<usize, CaptchaError> {
+    match n {
+        None => Ok(100),
+        Some(n) if n > 0 && n <= 1000 => Ok(n),
+        _ => Err(CaptchaError::InvalidParameters)
+    }
+}
+
 pub fn validate_solution(s: String) -> Result<String, CaptchaError> {
     if s.len() > 10 {
         return Err(CaptchaError::InvalidParameters);
@@ -0,0 +1,27 @@
+/// Version of the widget. It is the version of the service.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const SCRIPT: &str = include_str!("widget.js");
+const STYLE: &str = include_str!("widget.css");
+
+/// Returns the JavaScript of the widget.
+pub fn script() -> String {
+    SCRIPT.replace("{{version}}", VERSION)
+}
+
+/// Returns the stylesheet of the widget.
+pub fn style() -> String {
+    STYLE.replace("{{version}}", VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::widget::{script, VERSION};
+
+    #[test]
+    fn test_script() {
+        let s = script();
+        assert!(s.contains(VERSION));
+        assert!(!s.contains("{{version}}"));
+    }
+}
@@ -0,0 +1,235 @@
+/*
+ * rust-captcha widget {{version}}
+ *
+ * Renders a CAPTCHA into every element with the class "rust-captcha". When the CAPTCHA has been
+ * solved, the id of the CAPTCHA is written into a hidden form field. The server which receives
+ * the form redeems the token via POST /captcha/<token>/redeem. Each token can be redeemed once.
+ *
+ * The audio button reads out the characters of the CAPTCHA.
+ *
+ * Configuration via data-attributes:
+ *   data-endpoint    URL of the service (default: the URL from which the script was loaded)
+ *   data-difficulty  easy, medium, hard, animated-<difficulty> or auto (default: the default of the
+ *                    site or medium)
+ *   data-tries       maximum number of tries (default: the default of the site or 3)
+ *   data-ttl         seconds until the CAPTCHA expires (default: the default of the site or 300)
+ *   data-field       name of the hidden form field (default: captcha-token)
+ *   data-client      key of the site, sent in the X-Client-ID header (optional)
+ *
+ * If a site key is given, the widget shows the name and uses the color of the site.
+ */
+(function () {
+    "use strict";
+
+    var VERSION = "{{version}}";
+    var script = document.currentScript;
+    var defaultEndpoint = script && script.src ? script.src.replace(/\/widget\.js(\?.*)?$/, "") : "";
+
+    function el(tag, cls, parent) {
+        var e = document.createElement(tag);
+        if (cls) {
+            e.className = cls;
+        }
+        if (parent) {
+            parent.appendChild(e);
+        }
+        return e;
+    }
+
+    function Widget(root) {
+        var d = root.dataset;
+        this.root = root;
+        this.endpoint = (d.endpoint || defaultEndpoint).replace(/\/$/, "");
+        this.difficulty = d.difficulty || "default";
+        this.tries = d.tries || "default";
+        this.ttl = d.ttl || "default";
+        this.client = d.client;
+        this.id = null;
+        this.render(d.field || "captcha-token");
+        if (this.client) {
+            this.brand();
+        }
+        this.load();
+    }
+
+    Widget.prototype.render = function (field) {
+        var self = this;
+        this.image = el("img", "rust-captcha-image", this.root);
+        this.image.alt = "CAPTCHA";
+        var row = el("div", "rust-captcha-row", this.root);
+        this.input = el("input", "rust-captcha-input", row);
+        this.input.type = "text";
+        this.input.autocomplete = "off";
+        this.input.setAttribute("aria-label", "Characters shown in the image");
+        this.verify = el("button", "rust-captcha-button", row);
+        this.verify.type = "button";
+        this.verify.textContent = "Verify";
+        this.refresh = el("button", "rust-captcha-button", row);
+        this.refresh.type = "button";
+        this.refresh.textContent = "↻";
+        this.refresh.title = "New image";
+        this.listen = el("button", "rust-captcha-button", row);
+        this.listen.type = "button";
+        this.listen.textContent = "🔊";
+        this.listen.title = "Listen to the characters";
+        this.listen.setAttribute("aria-label", "Listen to the characters");
+        this.message = el("div", "rust-captcha-message", this.root);
+        this.message.setAttribute("aria-live", "polite");
+        this.token = el("input", null, this.root);
+        this.token.type = "hidden";
+        this.token.name = field;
+
+        this.verify.addEventListener("click", function () { self.check(); });
+        this.refresh.addEventListener("click", function () { self.reload(); });
+        this.listen.addEventListener("click", function () { self.play(); });
+        this.input.addEventListener("keydown", function (e) {
+            if (e.key === "Enter") {
+                e.preventDefault();
+                self.check();
+            }
+        });
+    };
+
+    // Sends a request to the service. If the service does not process the request, `failed` is
+    // called with the error code of the response.
+    Widget.prototype.request = function (path, done, failed, method) {
+        var self = this;
+        failed = failed || function (code) {
+            self.fail(code === 3 ? "Too many requests. Please wait a moment." : "The service is not available.");
+        };
+        var xhr = new XMLHttpRequest();
+        xhr.open(method || "POST", this.endpoint + path);
+        if (this.client) {
+            xhr.setRequestHeader("X-Client-ID", this.client);
+        }
+        xhr.onload = function () {
+            var r;
+            try {
+                r = JSON.parse(xhr.responseText);
+            } catch (e) {
+                return failed(1);
+            }
+            if (r.error_code !== 0) {
+                return failed(r.error_code);
+            }
+            done(r.result);
+        };
+        xhr.onerror = function () { failed(1); };
+        xhr.send();
+    };
+
+    Widget.prototype.state = function (cls, text) {
+        this.root.className = "rust-captcha" + (cls ? " rust-captcha-" + cls : "");
+        this.message.textContent = text;
+    };
+
+    Widget.prototype.fail = function (text) {
+        this.state("error", text);
+    };
+
+    Widget.prototype.show = function (c, text) {
+        this.id = c.id;
+        this.token.value = "";
+        this.image.src = c.gif ? "data:image/gif;base64," + c.gif : "data:image/png;base64," + c.png;
+        this.input.value = "";
+        this.input.disabled = false;
+        this.verify.disabled = false;
+        this.state(text ? "error" : null, text || "");
+    };
+
+    // Applies the branding of the site. Errors are ignored, the widget works without branding.
+    Widget.prototype.brand = function () {
+        var self = this;
+        this.request("/widget/site", function (site) {
+            var b = site.branding || {};
+            if (b.color) {
+                self.root.style.borderColor = b.color;
+                self.verify.style.backgroundColor = b.color;
+                self.verify.style.color = "#fff";
+            }
+            if (b.logo) {
+                var logo = el("img", "rust-captcha-logo");
+                logo.src = b.logo;
+                logo.alt = "";
+                self.root.insertBefore(logo, self.root.firstChild);
+            }
+            if (b.name) {
+                self.root.title = b.name;
+                self.image.alt = "CAPTCHA of " + b.name;
+            }
+        }, function () {}, "GET");
+    };
+
+    Widget.prototype.load = function (text) {
+        var self = this;
+        var path = "/widget/new/" + encodeURIComponent(this.difficulty) + "/" +
+            encodeURIComponent(this.tries) + "/" + encodeURIComponent(this.ttl);
+        this.request(path, function (c) { self.show(c, text); });
+    };
+
+    // Replaces the image of the current CAPTCHA. If the CAPTCHA cannot be refreshed anymore, a new
+    // CAPTCHA is created.
+    Widget.prototype.reload = function () {
+        var self = this;
+        if (!this.id) {
+            return this.load();
+        }
+        this.request("/widget/captcha/" + this.id + "/refresh",
+            function (c) { self.show(c); },
+            function (code) { code === 2 || code === 3 || code === 5 ? self.load() : self.fail("The service is not available."); });
+    };
+
+    // Plays the audio of the current CAPTCHA. A new audio is requested each time.
+    Widget.prototype.play = function () {
+        var self = this;
+        if (!this.id) {
+            return;
+        }
+        var audio = new Audio(this.endpoint + "/widget/captcha/" + this.id + "/audio");
+        var failed = function () { self.fail("The audio is not available."); };
+        audio.addEventListener("error", failed);
+        var p = audio.play();
+        if (p && p.catch) {
+            p.catch(failed);
+        }
+        this.input.focus();
+    };
+
+    Widget.prototype.check = function () {
+        var self = this;
+        var solution = this.input.value.trim();
+        if (!this.id || !solution) {
+            return;
+        }
+        this.request("/solution/" + this.id + "/" + encodeURIComponent(solution), function (r) {
+            if (r.solution === "accepted") {
+                self.token.value = self.id;
+                self.input.disabled = true;
+                self.verify.disabled = true;
+                self.state("verified", "Verified.");
+            } else if (r.trials_left > 0) {
+                self.input.value = "";
+                self.fail("Wrong solution. " + r.trials_left + (r.trials_left === 1 ? " try" : " tries") + " left.");
+            } else {
+                self.load("Wrong solution. Please solve the new CAPTCHA.");
+            }
+        });
+    };
+
+    function init() {
+        var roots = document.querySelectorAll(".rust-captcha");
+        for (var i = 0; i < roots.length; i++) {
+            if (!roots[i].rustCaptcha) {
+                roots[i].rustCaptcha = new Widget(roots[i]);
+            }
+        }
+    }
+
+    window.RustCaptcha = { version: VERSION, init: init };
+
+    if (document.readyState === "loading") {
+        document.addEventListener("DOMContentLoaded", init);
+    } else {
+        init();
+    }
+})();
@@ -0,0 +1,293 @@
+//! Types of the requests and responses of the CAPTCHA service. They are shared by the service and
+//! the client library.
+
+use serde::{Deserialize, Serialize};
+
+/// Error codes of the envelope in which each response is wrapped.
+pub mod error_code {
+    pub const PROCESSED: u32 = 0;
+    pub const INTERNAL_ERROR: u32 = 1;
+    pub const INVALID_PARAMETERS: u32 = 2;
+    pub const TOO_MANY_REQUESTS: u32 = 3;
+    pub const UNAUTHORIZED: u32 = 4;
+    pub const NOT_FOUND: u32 = 5;
+}
+
+/// The envelope of each response. If `error_code` is not `error_code::PROCESSED`, `result` is
+/// an empty string.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Envelope<T> {
+    pub error_code: u32,
+    pub error_msg: String,
+    pub result: T,
+}
+
+/// The difficulty of an image CAPTCHA.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy   => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard   => "hard",
+        }
+    }
+}
+
+/// Parameters of a new CAPTCHA.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewCaptchaRequest {
+    pub difficulty: Difficulty,
+    pub max_tries: u32,
+    pub ttl: u32,
+    /// Seed of the CAPTCHA. Only accepted by debug builds of the service.
+    pub seed: Option<u64>,
+}
+
+impl NewCaptchaRequest {
+    pub fn new(difficulty: Difficulty, max_tries: u32, ttl: u32) -> NewCaptchaRequest {
+        NewCaptchaRequest { difficulty, max_tries, ttl, seed: None }
+    }
+
+    /// Returns the path of the request, e.g. `/new/easy/3/60`.
+    pub fn path(&self) -> String {
+        let path = format!("/new/{}/{}/{}", self.difficulty.as_str(), self.max_tries, self.ttl);
+        match self.seed {
+            Some(seed) => format!("{}?seed={}", path, seed),
+            None => path
+        }
+    }
+}
+
+/// A new CAPTCHA. `png` contains the base64 encoded image. The solution is empty and omitted
+/// in responses which are sent to browsers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewCaptchaResponse {
+    pub id: String,
+    pub png: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub solution: String,
+}
+
+/// A new animated CAPTCHA. `gif` contains the base64 encoded animation. The solution is empty and
+/// omitted in responses which are sent to browsers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewAnimationResponse {
+    pub id: String,
+    pub gif: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub solution: String,
+}
+
+/// A new proof-of-work challenge.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewPowResponse {
+    pub id: String,
+    pub nonce: String,
+    pub bits: u32,
+}
+
+/// The verdict of a solution check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    /// The solution is correct.
+    Accepted,
+    /// The solution is wrong.
+    Incorrect { tries_left: usize },
+    /// There were no tries left.
+    Exhausted,
+    /// The CAPTCHA has expired.
+    Expired,
+    /// The CAPTCHA does not exist.
+    NotFound,
+    /// The CAPTCHA has already been solved.
+    AlreadyUsed,
+}
+
+impl Verdict {
+    /// Returns the value of `verdict` in a response.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Verdict::Accepted          => "accepted",
+            Verdict::Incorrect { .. }  => "incorrect",
+            Verdict::Exhausted         => "exhausted",
+            Verdict::Expired           => "expired",
+            Verdict::NotFound          => "not_found",
+            Verdict::AlreadyUsed       => "already_used",
+        }
+    }
+
+    /// Returns the value of `solution` in a response. Clients which do not know the verdict
+    /// get the same values as before.
+    fn legacy(&self) -> &'static str {
+        match *self {
+            Verdict::Accepted          => "accepted",
+            Verdict::Incorrect { .. }  => "incorrect",
+            Verdict::Exhausted         => "too many trials",
+            _                          => "not found",
+        }
+    }
+
+    pub fn tries_left(&self) -> usize {
+        match *self {
+            Verdict::Incorrect { tries_left } => tries_left,
+            _ => 0
+        }
+    }
+}
+
+/// The result of a solution check. `verdict` contains the code of the `Verdict`. `solution` is
+/// `accepted` if the solution was correct, otherwise it contains the reason why it was rejected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CaptchaSolutionResponse {
+    solution: String,
+    trials_left: usize,
+    #[serde(default)]
+    verdict: String,
+}
+
+impl CaptchaSolutionResponse {
+    pub fn new(verdict: Verdict) -> CaptchaSolutionResponse {
+        CaptchaSolutionResponse {
+            solution: String::from(verdict.legacy()),
+            trials_left: verdict.tries_left(),
+            verdict: String::from(verdict.code()),
+        }
+    }
+
+    pub fn result(&self) -> String {
+        self.solution.clone()
+    }
+
+    pub fn trials_left(&self) -> usize {
+        self.trials_left
+    }
+
+    pub fn accepted(&self) -> bool {
+        self.solution == "accepted"
+    }
+
+    /// Returns the verdict. Responses of older versions of the service, which do not contain the
+    /// verdict, are mapped by the value of `solution`.
+    pub fn verdict(&self) -> Verdict {
+        match (self.verdict.as_str(), self.solution.as_str()) {
+            ("accepted", _) | ("", "accepted") => Verdict::Accepted,
+            ("incorrect", _) | ("", "incorrect") => Verdict::Incorrect { tries_left: self.trials_left },
+            ("exhausted", _) | ("", "too many trials") => Verdict::Exhausted,
+            ("expired", _) => Verdict::Expired,
+            ("already_used", _) => Verdict::AlreadyUsed,
+            _ => Verdict::NotFound
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptchaState {
+    /// The CAPTCHA can be solved.
+    Pending,
+    /// The CAPTCHA has been solved.
+    Solved,
+    /// There are no tries left.
+    Exhausted,
+    /// The CAPTCHA has expired.
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CaptchaStatus {
+    id: String,
+    state: CaptchaState,
+    tries_left: usize,
+    expires_at: i64,
+}
+
+impl CaptchaStatus {
+    pub fn new(id: String, state: CaptchaState, tries_left: usize, expires_at: i64) -> CaptchaStatus {
+        CaptchaStatus { id, state, tries_left, expires_at }
+    }
+
+    pub fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn state(&self) -> CaptchaState {
+        self.state
+    }
+
+    pub fn tries_left(&self) -> usize {
+        self.tries_left
+    }
+
+    pub fn expires_at(&self) -> i64 {
+        self.expires_at
+    }
+}
+
+/// The result of redeeming the token of a solved CAPTCHA. `redeemed` is `true` only for the
+/// first redemption of a token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RedeemResponse {
+    id: String,
+    redeemed: bool,
+}
+
+impl RedeemResponse {
+    pub fn new(id: String, redeemed: bool) -> RedeemResponse {
+        RedeemResponse { id, redeemed }
+    }
+
+    pub fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn redeemed(&self) -> bool {
+        self.redeemed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CaptchaSolutionResponse, CaptchaState, Difficulty, Envelope, NewCaptchaRequest, Verdict};
+
+    #[test]
+    fn test_path() {
+        let mut r = NewCaptchaRequest::new(Difficulty::Easy, 3, 60);
+        assert_eq!(r.path(), "/new/easy/3/60");
+        r.seed = Some(7);
+        assert_eq!(r.path(), "/new/easy/3/60?seed=7");
+    }
+
+    #[test]
+    fn test_envelope() {
+        let s = r#"{"error_code":0,"error_msg":"processed","result":{"solution":"not found","trials_left":0,"verdict":"expired"}}"#;
+        let e: Envelope<CaptchaSolutionResponse> = serde_json::from_str(s).unwrap();
+        assert_eq!(e.result, CaptchaSolutionResponse::new(Verdict::Expired));
+        assert!(!e.result.accepted());
+        assert_eq!(serde_json::to_string(&CaptchaState::Exhausted).unwrap(), "\"exhausted\"");
+    }
+
+    #[test]
+    fn test_verdict() {
+        let all = [Verdict::Accepted, Verdict::Incorrect { tries_left: 2 }, Verdict::Exhausted,
+                   Verdict::Expired, Verdict::NotFound, Verdict::AlreadyUsed];
+        for v in &all {
+            let json = serde_json::to_string(&CaptchaSolutionResponse::new(*v)).unwrap();
+            let r: CaptchaSolutionResponse = serde_json::from_str(&json).unwrap();
+            assert_eq!(r.verdict(), *v);
+        }
+
+        // Responses without a verdict.
+        let r: CaptchaSolutionResponse = serde_json::from_str(r#"{"solution":"incorrect","trials_left":1}"#).unwrap();
+        assert_eq!(r.verdict(), Verdict::Incorrect { tries_left: 1 });
+        let r: CaptchaSolutionResponse = serde_json::from_str(r#"{"solution":"too many trials","trials_left":0}"#).unwrap();
+        assert_eq!(r.verdict(), Verdict::Exhausted);
+    }
+}